@@ -0,0 +1,86 @@
+// ndarray input for line_plot, save_columns_to_file and the other plots, behind the ndarray feature.
+// Contiguous f64 data is borrowed, everything else is copied once.
+use std::borrow::Cow;
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis};
+
+use crate::series::{IntoColumn, IntoSeries, Scalar, Series};
+
+impl<'a, T: Scalar> IntoColumn<'a> for ArrayView1<'a, T> {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        match self.to_slice() {
+            Some(values) => T::to_column(values),
+            None => Cow::Owned(self.iter().map(|v| v.to_f64()).collect()),
+        }
+    }
+}
+
+impl<'a, T: Scalar> IntoColumn<'a> for &'a Array1<T> {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        self.view().into_column()
+    }
+}
+
+impl<'a, T: Scalar> IntoColumn<'a> for Array1<T> {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        Cow::Owned(self.iter().map(|v| v.to_f64()).collect())
+    }
+}
+
+// Columns of the array, the first one is x and the others are y.
+// Columns are contiguous only in column major (Fortran) layout, e.g. Array2::zeros((n, m).f()).
+impl<'a, T: Scalar> IntoSeries<'a> for ArrayView2<'a, T> {
+    fn into_series(self) -> Series<'a> {
+        let mut columns = (0..self.ncols()).map(move |j| self.index_axis_move(Axis(1), j));
+        let x = columns.next().expect("No data columns");
+        Series::shared_x(x, columns)
+    }
+}
+
+impl<'a, T: Scalar> IntoSeries<'a> for &'a Array2<T> {
+    fn into_series(self) -> Series<'a> {
+        self.view().into_series()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{read_columns_from_file, save_columns_to_file};
+    use ndarray::{array, s, ShapeBuilder};
+
+    #[test]
+    fn columns() {
+        let a = array![1.0, 2.0, 3.0, 4.0];
+        assert!(matches!(a.view().into_column(), Cow::Borrowed(&[1.0, 2.0, 3.0, 4.0])));
+        assert!(matches!((&a).into_column(), Cow::Borrowed(_)));
+        // strided views and other types are copied
+        assert_eq!(a.slice(s![..;2]).into_column(), Cow::<[f64]>::Owned(vec![1.0, 3.0]));
+        assert!(matches!(a.slice(s![..;2]).into_column(), Cow::Owned(_)));
+        assert_eq!(array![1i32, -2].into_column(), Cow::<[f64]>::Owned(vec![1.0, -2.0]));
+    }
+
+    #[test]
+    fn series() {
+        let mut fortran = Array2::<f64>::zeros((3, 3).f());
+        fortran.assign(&array![[0.0, 1.0, 2.0], [1.0, 3.0, 4.0], [2.0, 5.0, 6.0]]);
+        let series = (&fortran).into_series();
+        assert_eq!(series.len(), 2);
+        assert_eq!((series.x(1), series.y(0), series.y(1)), (&[0.0, 1.0, 2.0][..], &[1.0, 3.0, 5.0][..], &[2.0, 4.0, 6.0][..]));
+        // column major columns are borrowed
+        assert_eq!(series.x(0).as_ptr(), fortran.as_ptr());
+        // row major columns give the same series
+        let rows = array![[0.0, 1.0, 2.0], [1.0, 3.0, 4.0], [2.0, 5.0, 6.0]];
+        assert_eq!(rows.view().into_series().columns(), series.columns());
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("arrays-{}", std::process::id()));
+        let data = array![[0.0, 1.5], [1.0, -2.25], [2.0, 1e-3]];
+        save_columns_to_file(&data, dir.to_str().unwrap(), "a.dat");
+        let (_, columns) = read_columns_from_file(dir.join("a.dat"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(columns, [data.column(0).to_vec(), data.column(1).to_vec()]);
+    }
+}
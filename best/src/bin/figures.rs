@@ -0,0 +1,79 @@
+// figures: renders the figures of a TOML manifest, see manifest.rs, e.g.
+// figures paper.toml --only iv_curve --force
+// With --watch the manifest and the data files are watched and changed figures rendered again.
+use std::path::PathBuf;
+
+use clap::Parser;
+use taylor_plotly_example::manifest::{manifest_inputs, render_manifest, BatchOptions, BatchResults, FigureStatus};
+use taylor_plotly_example::plot::shutdown_kaleido;
+use taylor_plotly_example::watch::{watch, WatchOptions};
+
+#[derive(Parser)]
+#[command(name = "figures", about = "Renders the figures of a manifest")]
+struct Args {
+    manifest: PathBuf,
+    /// Render the figures even if nothing changed
+    #[arg(long)]
+    force: bool,
+    /// Render only this figure, may be repeated
+    #[arg(long, value_name = "NAME")]
+    only: Vec<String>,
+    /// Figures rendered at the same time [default: the number of CPUs]
+    #[arg(short, long, default_value_t = 0, hide_default_value = true)]
+    jobs: usize,
+    /// Keep running and render changed figures again when the manifest or the data files change
+    #[arg(long)]
+    watch: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut options = BatchOptions::new();
+    options.force = args.force;
+    options.only = args.only.clone();
+    options.complete_lines = args.watch;
+    options.workers = args.jobs;
+
+    if args.watch {
+        // files added to the manifest later are watched after a restart
+        let inputs = manifest_inputs(&args.manifest).unwrap_or_else(|e| {
+            eprintln!("figures: {}", e);
+            std::process::exit(2);
+        });
+        watch(&inputs, &WatchOptions::new(), || {
+            let results = render_manifest(&args.manifest, &options)?;
+            // figures are rendered once with --force, then only when they change
+            options.force = false;
+            report(&results)
+        });
+    }
+    let result = render_manifest(&args.manifest, &options).map(|results| report(&results));
+    shutdown_kaleido();
+    match result {
+        Ok(Ok(())) => {},
+        Ok(Err(message)) | Err(message) => {
+            eprintln!("figures: {}", message);
+            std::process::exit(1);
+        },
+    }
+}
+
+// Prints a line per figure and the warnings, Err when some figures failed
+fn report(results: &BatchResults) -> Result<(), String> {
+    for warning in results.warnings.iter() {
+        eprintln!("figures: warning: {}", warning);
+    }
+    let results = &results.figures;
+    for result in results {
+        match &result.status {
+            FigureStatus::Rendered => println!("rendered   {}", result.name),
+            FigureStatus::Unchanged => println!("unchanged  {}", result.name),
+            FigureStatus::Failed(message) => println!("FAILED     {}: {}", result.name, message),
+        }
+    }
+    let failed = results.iter().filter(|r| matches!(r.status, FigureStatus::Failed(_))).count();
+    if failed > 0 {
+        return Err(format!("{} of {} figures failed", failed, results.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+// plotdat: line_plot of text data files from the command line, e.g.
+// plotdat IV_curve.dat -c 'x = 1e4*$1; y = $2' --xlab 'E, V/cm' --log-y -f pdf,svg
+// Settings may also come from a TOML file with the keys of a manifest's [defaults], e.g.
+// plotdat IV_curve.dat --config paper.toml --title 'I-V curve'
+// where the options given on the command line override the file.
+// With --watch the figure is rendered again whenever the data files or the config file change.
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use taylor_plotly_example::downsample::Downsample;
+use taylor_plotly_example::file::{read_columns_with, ParseOptions};
+use taylor_plotly_example::manifest::FigureEntry;
+use taylor_plotly_example::plot::{line_plot, shutdown_kaleido, LegendAl, LineOrPoints};
+use taylor_plotly_example::series::Series;
+use taylor_plotly_example::spec::ColumnSpec;
+use taylor_plotly_example::watch::{watch, WatchOptions};
+
+#[derive(Parser)]
+#[command(name = "plotdat", about = "Plots columns of text data files")]
+struct Args {
+    /// Data files, plain or compressed as .gz or .zst; Arrow IPC and Parquet with the arrow feature
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Column spec such as 'x = 1e4*$1; y = $3/$2', one for all files or one per file [default: x = $1, y = the others]
+    #[arg(short, long = "columns", value_name = "SPEC")]
+    columns: Vec<ColumnSpec>,
+    #[arg(short, long, default_value = "")]
+    title: String,
+    #[arg(long, default_value = "x")]
+    xlab: String,
+    #[arg(long, default_value = "y")]
+    ylab: String,
+    /// Legend of the next series, repeated for every series [default: the y spec or column name]
+    #[arg(short, long = "legend", value_name = "TEXT")]
+    legends: Vec<String>,
+    /// top-right, top-left, top-center, bottom-right, bottom-left, bottom-center, center-right or center-left
+    #[arg(long, default_value = "top-right")]
+    legend_pos: LegendAl,
+    #[arg(long)]
+    no_legend: bool,
+    #[arg(long)]
+    log_x: bool,
+    #[arg(long)]
+    log_y: bool,
+    #[arg(long, num_args = 2, value_names = ["MIN", "MAX"], allow_negative_numbers = true)]
+    x_range: Option<Vec<f64>>,
+    #[arg(long, num_args = 2, value_names = ["MIN", "MAX"], allow_negative_numbers = true)]
+    y_range: Option<Vec<f64>>,
+    /// Output file name without extension [default: name of the first data file]
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Comma separated output formats: pdf, png, svg, jpeg, webp, eps, html or json
+    #[arg(short, long = "format", value_delimiter = ',', default_value = "pdf,png")]
+    formats: Vec<String>,
+    /// Put plotly.js and the fonts into html output, which then works without network
+    #[arg(long)]
+    offline_html: bool,
+    #[arg(long, default_value_t = 1600)]
+    width: usize,
+    #[arg(long, default_value_t = 1080)]
+    height: usize,
+    /// line, points or both
+    #[arg(long, default_value = "line")]
+    style: LineOrPoints,
+    /// off, lttb or min-max
+    #[arg(long, default_value = "off")]
+    downsample: Downsample,
+    #[arg(long)]
+    no_grid: bool,
+    /// Font of all text, a CSS font family list such as 'DejaVu Serif, serif'
+    #[arg(long, default_value = "Serif")]
+    font_family: String,
+    /// TOML file of plot settings with the keys of a manifest's [defaults], see manifest.rs
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Keep running and render again when the data files or the config file change
+    #[arg(long)]
+    watch: bool,
+    // the options given on the command line, which override the config file
+    #[arg(skip)]
+    given: Vec<String>,
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.given = matches.ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect();
+    if args.columns.len() > 1 && args.columns.len() != args.files.len() {
+        eprintln!("plotdat: got {} column specs for {} files", args.columns.len(), args.files.len());
+        std::process::exit(2);
+    }
+    if args.watch {
+        let mut inputs = args.files.clone();
+        inputs.extend(args.config.clone());
+        watch(&inputs, &WatchOptions::new(), || render(&args));
+    }
+    let result = render(&args);
+    shutdown_kaleido();
+    if let Err(message) = result {
+        eprintln!("plotdat: {}", message);
+        std::process::exit(2);
+    }
+}
+
+fn render(args: &Args) -> Result<(), String> {
+    // the last line of a file that is still being written may be cut short
+    let mut options = ParseOptions::new();
+    options.complete_lines = args.watch;
+
+    // x and the y against it of every file, and the default legend of every series
+    let mut data = Vec::new();
+    let mut labels = Vec::new();
+    for (k, path) in args.files.iter().enumerate() {
+        let spec = args.columns.get(k).or(args.columns.first()).cloned().unwrap_or_else(|| ColumnSpec::parse("").unwrap());
+        let (names, columns) = read_columns_with(path, &options).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (x, ys) = spec.apply(&names, &columns).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        for label in spec.y_labels(&names, columns.len()).into_iter().take(ys.len()) {
+            labels.push(if args.files.len() > 1 { format!("{}: {}", stem, label) } else { label });
+        }
+        data.push((x, ys));
+    }
+    let mut series = Series::empty();
+    for (x, ys) in data.iter() {
+        series.push_shared_x(x, ys);
+    }
+    let lines_number = series.len();
+
+    let flnm = args.output.clone().unwrap_or_else(|| {
+        args.files[0].with_extension("").to_string_lossy().trim_end_matches(".dat").to_string()
+    });
+    let mut legends = args.legends.clone();
+    legends.extend(labels.into_iter().skip(args.legends.len()));
+
+    // read at every render, so that --watch picks up its changes
+    let config = match &args.config {
+        Some(path) => FigureEntry::load(path)?,
+        None => FigureEntry::default(),
+    };
+    let mut plot_par = config.plot_par(&flnm, legends, lines_number)?;
+    // an option given on the command line, or with its default where the config file has no setting
+    let cli = |id: &str, in_config: bool| args.given.iter().any(|given| given == id) || !in_config;
+    if cli("title", config.title.is_some()) {
+        plot_par.title = args.title.clone();
+    }
+    if cli("xlab", config.xlab.is_some()) {
+        plot_par.xlab = args.xlab.clone();
+    }
+    if cli("ylab", config.ylab.is_some()) {
+        plot_par.ylab = args.ylab.clone();
+    }
+    if cli("width", config.width.is_some()) {
+        plot_par.width = args.width;
+    }
+    if cli("height", config.height.is_some()) {
+        plot_par.height = args.height;
+    }
+    if cli("legend_pos", config.legend_pos.is_some()) {
+        plot_par.legend_al = args.legend_pos;
+    }
+    if cli("no_legend", config.show_legend.is_some()) {
+        plot_par.show_legend = !args.no_legend;
+    }
+    if cli("log_x", config.log_x.is_some()) {
+        plot_par.log_x = args.log_x;
+    }
+    if cli("log_y", config.log_y.is_some()) {
+        plot_par.log_y = args.log_y;
+    }
+    if let Some(range) = &args.x_range {
+        plot_par.custom_range_x = true;
+        plot_par.range_x = [range[0], range[1]];
+    }
+    if let Some(range) = &args.y_range {
+        plot_par.custom_range_y = true;
+        plot_par.range_y = [range[0], range[1]];
+    }
+    if cli("formats", config.formats.is_some()) {
+        plot_par.formats = args.formats.clone();
+    }
+    if cli("offline_html", config.offline_html.is_some()) {
+        plot_par.offline_html = args.offline_html;
+    }
+    if cli("style", config.style.is_some()) {
+        plot_par.line_or_points = vec![args.style; lines_number.max(100)];
+    }
+    if cli("downsample", config.downsample.is_some()) {
+        plot_par.downsample = args.downsample;
+    }
+    if cli("no_grid", config.show_grid.is_some()) {
+        plot_par.show_grid = !args.no_grid;
+    }
+    if cli("font_family", config.font_family.is_some()) {
+        plot_par.font_family = args.font_family.clone();
+    }
+
+    line_plot(series, &plot_par);
+    Ok(())
+}
@@ -0,0 +1,177 @@
+// Arrow IPC and Parquet files of named columns with units and metadata, behind the arrow feature.
+// The format follows the extension: .parquet for Parquet (zstd compressed), anything else Arrow IPC.
+// read_columns_with reads files with the extensions of COLUMNAR_EXTENSIONS through read_arrow.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, AsArray, Float64Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Float64Type, Schema};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use crate::npy::create_file;
+use crate::plot::PlotPar;
+use crate::series::IntoSeries;
+
+pub(crate) const COLUMNAR_EXTENSIONS: [&str; 4] = ["arrow", "ipc", "feather", "parquet"];
+
+// rows per record batch, so that only one batch is copied into Arrow buffers at a time
+const BATCH_ROWS: usize = 65536;
+
+// Contents of a file read with read_arrow
+pub struct Table {
+    pub names: Vec<String>,
+    // empty for columns written without a unit
+    pub units: Vec<String>,
+    pub metadata: HashMap<String, String>,
+    pub columns: Vec<Vec<f64>>,
+}
+
+// Title and axis labels, to be stored with the data
+pub fn plot_metadata(plot_par: &PlotPar) -> Vec<(&str, &str)> {
+    vec![("title", &plot_par.title), ("xlab", &plot_par.xlab), ("ylab", &plot_par.ylab)]
+}
+
+// Columns as in save_columns_to_file, shorter columns padded with nulls.
+// Columns without a name are column_1, column_2, ...
+pub fn save_columns_to_arrow<'a, S: IntoSeries<'a>>(series: S, names: &[&str], units: &[&str], metadata: &[(&str, &str)], dir_name: &str, file_name: &str) {
+    let series = series.into_series();
+    let columns = series.columns();
+    let fields: Vec<Field> = (0..columns.len()).map(|k| {
+        let name = names.get(k).map(|name| name.to_string()).unwrap_or_else(|| format!("column_{}", k + 1));
+        let field = Field::new(name, DataType::Float64, true);
+        match units.get(k) {
+            Some(unit) if !unit.is_empty() => field.with_metadata(HashMap::from([("unit".to_string(), unit.to_string())])),
+            _ => field,
+        }
+    }).collect();
+    let metadata = metadata.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+
+    let file_path: PathBuf = [dir_name, file_name].iter().collect();
+    let file = create_file(dir_name, file_name);
+    let rows = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+    let batches = (0..rows).step_by(BATCH_ROWS).map(|start| {
+        let end = (start + BATCH_ROWS).min(rows);
+        let arrays: Vec<ArrayRef> = columns.iter()
+            .map(|c| Arc::new((start..end).map(|i| c.get(i).cloned()).collect::<Float64Array>()) as ArrayRef)
+            .collect();
+        RecordBatch::try_new(schema.clone(), arrays).expect("Columns don't match the schema")
+    });
+
+    if is_parquet(&file_path) {
+        let props = WriterProperties::builder().set_compression(Compression::ZSTD(ZstdLevel::default())).build();
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(props)).unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+        for batch in batches {
+            writer.write(&batch).unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+        }
+        writer.close().unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+    } else {
+        let mut writer = FileWriter::try_new(file, &schema).unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+        for batch in batches {
+            writer.write(&batch).unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+        }
+        writer.finish().unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+    }
+}
+
+// Numeric columns as f64, nulls as NaN except for the padding at the end of shorter columns.
+// Files that aren't Arrow IPC or Parquet and columns that aren't numeric give ErrorKind::InvalidData.
+pub fn read_arrow<P: AsRef<Path>>(path: P) -> io::Result<Table> {
+    let path = path.as_ref();
+    let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+    let file = File::open(path)?;
+    let (schema, batches): (_, Box<dyn Iterator<Item = _>>) = if is_parquet(path) {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).map_err(|e| invalid(e.to_string()))?;
+        let schema = builder.schema().clone();
+        let reader = builder.build().map_err(|e| invalid(e.to_string()))?;
+        (schema, Box::new(reader.map(|batch| batch.map_err(|e| e.to_string()))))
+    } else {
+        let reader = FileReader::try_new(file, None).map_err(|e| invalid(e.to_string()))?;
+        (reader.schema(), Box::new(reader.map(|batch| batch.map_err(|e| e.to_string()))))
+    };
+
+    let mut columns: Vec<Vec<Option<f64>>> = vec![Vec::new(); schema.fields().len()];
+    for batch in batches {
+        let batch = batch.map_err(invalid)?;
+        for ((column, array), field) in columns.iter_mut().zip(batch.columns()).zip(schema.fields()) {
+            let array = cast(array, &DataType::Float64)
+                .map_err(|_| invalid(format!("column {} of type {} is not numeric", field.name(), array.data_type())))?;
+            column.extend(array.as_primitive::<Float64Type>().iter());
+        }
+    }
+
+    Ok(Table {
+        names: schema.fields().iter().map(|field| field.name().clone()).collect(),
+        units: schema.fields().iter().map(|field| field.metadata().get("unit").cloned().unwrap_or_default()).collect(),
+        metadata: schema.metadata().clone(),
+        columns: columns.into_iter().map(|mut column| {
+            while column.last() == Some(&None) {
+                column.pop();
+            }
+            column.into_iter().map(|v| v.unwrap_or(f64::NAN)).collect()
+        }).collect(),
+    })
+}
+
+pub(crate) fn is_columnar(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).map(|ext| COLUMNAR_EXTENSIONS.contains(&ext.to_lowercase().as_str())).unwrap_or(false)
+}
+
+fn is_parquet(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "parquet").unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::series::Series;
+    use crate::spec::{load_series, ColumnSpec};
+    use crate::tokenizer::{read_columns_with, ParseOptions};
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("columnar-{}", std::process::id()));
+        let dir_name = dir.to_str().unwrap();
+        let x = vec![0.0, 1.0, 2.0];
+        let ys = vec![vec![1.0, f64::NAN, 3.0], vec![4.0, 5.0]];
+        for file_name in ["a.arrow", "a.parquet"] {
+            save_columns_to_arrow(Series::shared_x(&x, &ys), &["t", "v"], &["s", ""], &[("title", "T")], dir_name, file_name);
+            let path = dir.join(file_name);
+            let table = read_arrow(&path).unwrap();
+            assert_eq!(table.names, ["t", "v", "column_3"]);
+            assert_eq!(table.units, ["s", "", ""]);
+            assert_eq!(table.metadata, HashMap::from([("title".to_string(), "T".to_string())]));
+            assert_eq!(table.columns.len(), 3);
+            assert_eq!(table.columns[0], x);
+            assert!(table.columns[1][1].is_nan());
+            // the padding of the shorter column is dropped
+            assert_eq!(table.columns[2], [4.0, 5.0]);
+
+            let (names, columns) = read_columns_with(&path, &ParseOptions::new()).unwrap();
+            assert_eq!((names, columns.len()), (table.names, 3));
+            let series = load_series(&path, &"x = $t; y = 2*$column_3".parse::<ColumnSpec>().unwrap());
+            assert_eq!((series.x(0), series.y(0)), (&x[..], &[8.0, 10.0][..]));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_files() {
+        let path = std::env::temp_dir().join(format!("columnar-{}.parquet", std::process::id()));
+        assert_eq!(read_arrow(&path).err().map(|e| e.kind()), Some(ErrorKind::NotFound));
+        std::fs::write(&path, "1 2\n").unwrap();
+        assert_eq!(read_arrow(&path).err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+        assert!(read_columns_with(&path, &ParseOptions::new()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
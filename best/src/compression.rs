@@ -0,0 +1,113 @@
+// Transparent compression of text data files by extension: .gz for gzip, .zst for zstd.
+// Data is compressed and decompressed while streaming, never held in memory as a whole.
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+pub(crate) enum Writer {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Writer {
+    pub fn create(path: &Path) -> io::Result<Writer> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match extension(path) {
+            Some("gz") => Writer::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Some("zst") => Writer::Zstd(zstd::Encoder::new(file, 0)?),
+            _ => Writer::Plain(file),
+        })
+    }
+
+    // Makes everything written so far readable: compressed files end the current gzip member
+    // or zstd frame and start a new one, which the readers decode as one continuous stream
+    pub fn checkpoint(self) -> io::Result<Writer> {
+        Ok(match self {
+            Writer::Plain(mut w) => {
+                w.flush()?;
+                Writer::Plain(w)
+            },
+            Writer::Gzip(w) => {
+                let mut file = w.finish()?;
+                file.flush()?;
+                Writer::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            },
+            Writer::Zstd(w) => {
+                let mut file = w.finish()?;
+                file.flush()?;
+                Writer::Zstd(zstd::Encoder::new(file, 0)?)
+            },
+        })
+    }
+
+    // Writes the end of the compressed stream; dropping a Writer without finish loses it
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Writer::Plain(mut w) => w.flush(),
+            Writer::Gzip(w) => w.finish()?.flush(),
+            Writer::Zstd(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Plain(w) => w.write(buf),
+            Writer::Gzip(w) => w.write(buf),
+            Writer::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Plain(w) => w.flush(),
+            Writer::Gzip(w) => w.flush(),
+            Writer::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+pub(crate) fn open_reader(path: &Path) -> io::Result<BufReader<Box<dyn Read>>> {
+    let file = File::open(path)?;
+    Ok(BufReader::new(match extension(path) {
+        Some("gz") => Box::new(MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    }))
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|ext| ext.to_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> String {
+        let mut text = String::new();
+        open_reader(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    // what is written before a checkpoint is readable while the file is still open
+    #[test]
+    fn checkpoint_round_trip() {
+        for ext in ["dat", "gz", "zst"] {
+            let path = std::env::temp_dir().join(format!("compression-{}.{}", std::process::id(), ext));
+            let mut w = Writer::create(&path).unwrap();
+            w.write_all(b"1 2\n3 4\n").unwrap();
+            let mut w = w.checkpoint().unwrap();
+            assert_eq!(read(&path), "1 2\n3 4\n", "{}", ext);
+            w.write_all(b"5 6\n").unwrap();
+            w.finish().unwrap();
+            assert_eq!(read(&path), "1 2\n3 4\n5 6\n", "{}", ext);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Convergence studies: error versus step size (or N) on log-log axes
+use plotly::common::{Anchor, Font};
+use plotly::layout::{Annotation, Shape, ShapeLine, ShapeType};
+use crate::plot::{make_layout, make_trace, write_plot, PlotPar, Style};
+use crate::render::Figure;
+use crate::series::IntoSeries;
+
+// Vertical distance between the fitted line and the slope triangle, in decades
+const TRIANGLE_OFFSET: f64 = 0.2;
+// Width of the slope triangle as a fraction of the fit range, in decades
+const TRIANGLE_WIDTH: f64 = 0.3;
+
+// error ≈ constant * h^order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderFit {
+    pub order: f64,
+    pub constant: f64,
+    pub h_min: f64,
+    pub h_max: f64,
+}
+
+// Least squares fit of log(error) versus log(h) over the points with h inside range;
+// None with fewer than two positive points there or when they all have the same h
+pub fn fit_order(h: &[f64], err: &[f64], range: [f64; 2]) -> Option<OrderFit> {
+    let lo = range[0].min(range[1]);
+    let hi = range[0].max(range[1]);
+    let points: Vec<(f64, f64)> = h.iter().zip(err.iter())
+        .filter(|(h, e)| **h >= lo && **h <= hi && **h > 0.0 && **e > 0.0 && e.is_finite())
+        .map(|(h, e)| (h.ln(), e.ln()))
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let order = sxy / sxx;
+    let h_min = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min).exp();
+    let h_max = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max).exp();
+    Some(OrderFit {
+        order,
+        constant: (mean_y - order * mean_x).exp(),
+        h_min,
+        h_max,
+    })
+}
+
+// Plots error (y) versus h (x) for every series on log-log axes, fits the observed order
+// over fit_range and marks each series with a slope triangle labelled with the order.
+// A series that can't be fitted there, see fit_order, is plotted without one.
+pub fn convergence_plot<'a, S: IntoSeries<'a>>(series: S, fit_range: [f64; 2], plot_par: &PlotPar) -> Vec<Option<OrderFit>> {
+    let series = series.into_series();
+    let mut plot_par = plot_par.clone();
+    plot_par.log_x = true;
+    plot_par.log_y = true;
+    let style = Style::new(&plot_par);
+
+    let mut layout = make_layout(&plot_par, &style);
+    let mut fits = Vec::new();
+
+    for l in 0..series.len() {
+        let fit = fit_order(series.x(l), series.y(l), fit_range);
+        if let Some(fit) = &fit {
+            for shape in slope_triangle(fit, &plot_par, &style, l) {
+                layout.add_shape(shape);
+            }
+            for annotation in slope_labels(fit, &plot_par, &style, l) {
+                layout.add_annotation(annotation);
+            }
+        }
+        fits.push(fit);
+    }
+
+    let mut figure = Figure::new(layout);
+    for l in 0..series.len() {
+        figure.add_trace(make_trace(series.x(l), series.y(l), l, &plot_par, &style));
+    }
+
+    write_plot(&figure, &plot_par);
+    fits
+}
+
+// Corners of the triangle in log10 units: the two ends of the hypotenuse and the right angle
+fn triangle_corners(fit: &OrderFit) -> [(f64, f64); 3] {
+    let lx_min = fit.h_min.log10();
+    let lx_max = fit.h_max.log10();
+    let mid = 0.5 * (lx_min + lx_max);
+    let half = 0.5 * TRIANGLE_WIDTH * (lx_max - lx_min);
+    let line = |lx: f64| fit.constant.log10() + fit.order * lx;
+    let a = (mid - half, line(mid - half) - TRIANGLE_OFFSET);
+    let b = (mid + half, line(mid + half) - TRIANGLE_OFFSET);
+    let (low, high) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+    [low, high, (high.0, low.1)]
+}
+
+// Axes of log type take shape and annotation positions as log10 of the data values
+fn slope_triangle(fit: &OrderFit, plot_par: &PlotPar, style: &Style, l: usize) -> Vec<Shape> {
+    let corners = triangle_corners(fit);
+    let color = crate::plot::color(plot_par, l);
+    (0..3).map(|i| {
+        let (x0, y0) = corners[i];
+        let (x1, y1) = corners[(i + 1) % 3];
+        Shape::new()
+            .shape_type(ShapeType::Line)
+            .x_ref("x")
+            .y_ref("y")
+            .x0(x0)
+            .y0(y0)
+            .x1(x1)
+            .y1(y1)
+            .line(ShapeLine::new().color(color).width(style.thick as f64))
+    }).collect()
+}
+
+fn slope_labels(fit: &OrderFit, plot_par: &PlotPar, style: &Style, l: usize) -> Vec<Annotation> {
+    let [low, high, corner] = triangle_corners(fit);
+    let font = Font::new().size(style.fsz_legend).color(crate::plot::color(plot_par, l)).family(&plot_par.font_family);
+    let leg_anchor = if corner.0 >= low.0 { Anchor::Left } else { Anchor::Right };
+    let base = Annotation::new()
+        .text("1")
+        .x_ref("x")
+        .y_ref("y")
+        .x(0.5 * (low.0 + corner.0))
+        .y(corner.1)
+        .x_anchor(Anchor::Center)
+        .y_anchor(Anchor::Top)
+        .show_arrow(false)
+        .font(font.clone());
+    let leg = Annotation::new()
+        .text(format!("{:.2}", fit.order.abs()))
+        .x_ref("x")
+        .y_ref("y")
+        .x(corner.0)
+        .y(0.5 * (high.1 + corner.1))
+        .x_anchor(leg_anchor)
+        .y_anchor(Anchor::Middle)
+        .show_arrow(false)
+        .font(font);
+    vec![base, leg]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_order() {
+        let h = [0.1, 0.05, 0.025, 0.0125, 1.0];
+        let err: Vec<f64> = h.iter().map(|h| 3.0 * h * h).collect();
+        let fit = fit_order(&h, &err, [0.01, 0.2]).unwrap();
+        assert!((fit.order - 2.0).abs() < 1e-12 && (fit.constant - 3.0).abs() < 1e-9);
+        assert!((fit.h_min - 0.0125).abs() < 1e-12 && (fit.h_max - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn too_few_points() {
+        assert_eq!(fit_order(&[0.1, 0.01], &[1e-2, 1e-4], [0.05, 1.0]), None);
+        assert_eq!(fit_order(&[0.1, 0.1], &[1e-2, 2e-2], [0.0, 1.0]), None);
+        assert_eq!(fit_order(&[0.1, 0.01], &[1e-2, 0.0], [0.0, 1.0]), None);
+    }
+}
@@ -0,0 +1,252 @@
+// Parametric curves (x(t), y(t)) and polar curves r(θ)
+use plotly::common::{Font, Line, Marker, MarkerSymbol, Mode, Title};
+use plotly::layout::{Annotation, Margin};
+use plotly::{Layout, ScatterPolar};
+use serde_json::json;
+use std::borrow::Cow;
+
+use crate::plot::{color, make_layout, make_legend, make_trace, write_plot, LineOrPoints, PlotPar, Style};
+use crate::render::{Figure, Trace};
+use crate::series::IntoSeries;
+
+const PARAMETRIC_POINTS: usize = 1000;
+
+// Samples the curve at n equally spaced values of t
+pub fn sample_parametric(f: &dyn Fn(f64) -> (f64, f64), t_range: [f64; 2], n: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = n.max(2);
+    let dt = (t_range[1] - t_range[0]) / ((n - 1) as f64);
+    (0..n).map(|i| f(t_range[0] + i as f64 * dt)).unzip()
+}
+
+// Plots precomputed curves; arrows is the number of direction arrows drawn along each curve
+pub fn parametric_plot<'a, S: IntoSeries<'a>>(curves: S, arrows: usize, plot_par: &PlotPar) {
+    let curves = curves.into_series();
+    let style = Style::new(plot_par);
+    let mut layout = make_layout(plot_par, &style);
+    for l in 0..curves.len() {
+        for annotation in direction_arrows(curves.x(l), curves.y(l), arrows, l, plot_par, &style) {
+            layout.add_annotation(annotation);
+        }
+    }
+
+    let mut figure = Figure::new(layout);
+    for l in 0..curves.len() {
+        figure.add_trace(make_trace(curves.x(l), curves.y(l), l, plot_par, &style));
+    }
+
+    write_plot(&figure, plot_par);
+}
+
+// Samples every curve over t_range, plots them and returns the sampled series
+pub fn parametric_function_plot(curves: &[&dyn Fn(f64) -> (f64, f64)], t_range: [f64; 2], arrows: usize, plot_par: &PlotPar) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let (x, y): (Vec<Vec<f64>>, Vec<Vec<f64>>) = curves.iter()
+        .map(|f| sample_parametric(*f, t_range, PARAMETRIC_POINTS))
+        .unzip();
+    parametric_plot(x.iter().zip(&y).collect::<Vec<_>>(), arrows, plot_par);
+    (x, y)
+}
+
+// Arrows evenly spaced along the curve, spacing measured relative to the x and y spans
+fn direction_arrows(x: &[f64], y: &[f64], arrows: usize, l: usize, plot_par: &PlotPar, style: &Style) -> Vec<Annotation> {
+    // annotations on log axes are positioned in log10 units
+    let to_axis = |v: f64, log: bool| if log { v.log10() } else { v };
+    let points: Vec<(f64, f64)> = x.iter().zip(y.iter())
+        .map(|(&x, &y)| (to_axis(x, plot_par.log_x), to_axis(y, plot_par.log_y)))
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .collect();
+    if arrows == 0 || points.len() < 2 {
+        return Vec::new();
+    }
+
+    let span = |values: Vec<f64>| {
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        if max > min { max - min } else { 1.0 }
+    };
+    let x_span = span(points.iter().map(|p| p.0).collect());
+    let y_span = span(points.iter().map(|p| p.1).collect());
+
+    let mut length = vec![0.0];
+    for i in 1..points.len() {
+        let dx = (points[i].0 - points[i-1].0) / x_span;
+        let dy = (points[i].1 - points[i-1].1) / y_span;
+        length.push(length[i-1] + dx.hypot(dy));
+    }
+    let total = length[length.len() - 1];
+
+    let mut annotations = Vec::new();
+    for k in 0..arrows {
+        let s = (k as f64 + 0.5) / arrows as f64 * total;
+        let i = length.iter().position(|&len| len >= s).unwrap_or(points.len() - 1).max(1);
+        annotations.push(
+            Annotation::new()
+                .text("")
+                .x_ref("x")
+                .y_ref("y")
+                .ax_ref("x")
+                .ay_ref("y")
+                .x(points[i].0)
+                .y(points[i].1)
+                .ax(points[i-1].0)
+                .ay(points[i-1].1)
+                .show_arrow(true)
+                .arrow_head(2)
+                .arrow_size(1.5)
+                .arrow_width(style.thick as f64)
+                .arrow_color(color(plot_par, l))
+        );
+    }
+    annotations
+}
+
+// Plots r(θ) on polar axes, θ (x of the series) in radians and r as y.
+// The radial axis takes ylab, log_y and range_y.
+pub fn polar_plot<'a, S: IntoSeries<'a>>(series: S, plot_par: &PlotPar) {
+    let series = series.into_series();
+    let style = Style::new(plot_par);
+    let forecol = style.forecol;
+
+    let title = Title::new(&plot_par.title)
+        .font(Font::new().size(style.fsz_title).family(&plot_par.font_family).color(forecol));
+
+    let mut figure = Figure::new(Layout::new()
+        .width(plot_par.width)
+        .height(plot_par.height)
+        .font(Font::new().size(style.fsz_ticks))
+        .title(title)
+        .legend(make_legend(plot_par, &style))
+        .show_legend(plot_par.show_legend)
+        .paper_background_color(style.bgcol)
+        .margin(Margin::new()
+            .left((100.0 * plot_par.font_scale) as usize)
+            .bottom((75.0 * plot_par.font_scale) as usize)
+            .top((75.0 * plot_par.font_scale) as usize)
+        ));
+
+    // the plotly crate has no polar layout, so it is added to the JSON directly
+    let axis = json!({
+        "showline": true,
+        "linecolor": forecol,
+        "linewidth": style.thick,
+        "ticks": "outside",
+        "ticklen": 9,
+        "tickwidth": style.medium,
+        "tickcolor": forecol,
+        "tickfont": { "color": forecol },
+        "showgrid": plot_par.show_grid,
+        "gridcolor": style.gridcol,
+        "gridwidth": style.thick,
+    });
+    let mut radial_axis = axis.clone();
+    radial_axis["title"] = json!({
+        "text": plot_par.ylab,
+        "font": { "size": style.fsz_axes, "color": forecol, "family": plot_par.font_family },
+    });
+    radial_axis["angle"] = json!(90);
+    radial_axis["tickangle"] = json!(90);
+    if plot_par.log_y {
+        radial_axis["type"] = json!("log");
+        radial_axis["exponentformat"] = json!("e");
+    }
+    if plot_par.custom_range_y {
+        radial_axis["range"] = json!(plot_par.range_y);
+    }
+    let mut angular_axis = axis;
+    angular_axis["direction"] = json!("counterclockwise");
+
+    figure.layout["polar"] = json!({
+        "bgcolor": "rgba(0, 0, 0, 0)",
+        "radialaxis": radial_axis,
+        "angularaxis": angular_axis,
+    });
+
+    for l in 0..series.len() {
+        let degrees: Vec<f64> = series.x(l).iter().map(|t| t.to_degrees()).collect();
+        let trace = ScatterPolar::new(Vec::<f64>::new(), Vec::<f64>::new()).name(&plot_par.legends[l]);
+        let line = Line::new().color(color(plot_par, l)).width(style.medium as f64).dash(plot_par.dashes[l].clone());
+        let marker = Marker::new().size(style.msize).color(color(plot_par, l)).symbol(MarkerSymbol::Circle);
+        let trace = match plot_par.line_or_points[l] {
+            LineOrPoints::Line => trace.mode(Mode::Lines).line(line),
+            LineOrPoints::Points => trace.mode(Mode::Markers).marker(marker),
+            LineOrPoints::LineAndPoints => trace.mode(Mode::LinesMarkers).line(line).marker(marker),
+        };
+        figure.add_trace(Trace::new(trace, vec![("theta", Cow::Owned(degrees)), ("r", Cow::Borrowed(series.y(l)))]));
+    }
+
+    write_plot(&figure, plot_par);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::series::Series;
+    use serde_json::Value;
+    use std::f64::consts::PI;
+
+    // The figure that plot writes with plot_par, as plotly.js takes it
+    fn figure(name: &str, plot_par: &mut PlotPar, plot: impl FnOnce(&PlotPar)) -> Value {
+        let flnm = std::env::temp_dir().join(format!("curves-{}-{}", name, std::process::id()));
+        plot_par.flnm = flnm.to_string_lossy().to_string();
+        plot_par.formats = vec!["json".to_string()];
+        plot(plot_par);
+        let path = flnm.with_extension("json");
+        let figure = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        figure
+    }
+
+    fn plot_par() -> PlotPar {
+        PlotPar::new(800, 600, "x", "y", "", "", vec!["a".to_string(), "b".to_string()])
+    }
+
+    #[test]
+    fn sampling() {
+        let (x, y) = sample_parametric(&|t| (t.cos(), t.sin()), [0.0, PI], 5);
+        assert_eq!(x.len(), 5);
+        assert_eq!((x[0], y[0]), (1.0, 0.0));
+        assert!((x[4] + 1.0).abs() < 1e-12 && y[4].abs() < 1e-12);
+        // at least both ends
+        assert_eq!(sample_parametric(&|t| (t, t), [0.0, 1.0], 0), (vec![0.0, 1.0], vec![0.0, 1.0]));
+    }
+
+    #[test]
+    fn parametric() {
+        let circle = |t: f64| (t.cos(), t.sin());
+        let line = |t: f64| (t, 2.0 * t);
+        let mut plot_par = plot_par();
+        let mut sampled = (Vec::new(), Vec::new());
+        let figure = figure("parametric", &mut plot_par, |plot_par| {
+            sampled = parametric_function_plot(&[&circle, &line], [0.0, 1.0], 3, plot_par);
+        });
+        assert_eq!((sampled.0.len(), sampled.0[0].len(), sampled.1[1].len()), (2, PARAMETRIC_POINTS, PARAMETRIC_POINTS));
+        let traces = figure["data"].as_array().unwrap();
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[1]["x"].as_array().unwrap().len(), PARAMETRIC_POINTS);
+        // arrows per curve
+        assert_eq!(figure["layout"]["annotations"].as_array().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn polar_layout() {
+        let theta = vec![0.0, PI / 2.0, PI];
+        let r = vec![vec![1.0, 2.0, 3.0], vec![3.0, 2.0, 1.0]];
+        let mut plot_par = plot_par();
+        plot_par.log_y = true;
+        plot_par.custom_range_y = true;
+        plot_par.range_y = [0.0, 1.0];
+        let figure = figure("polar", &mut plot_par, |plot_par| polar_plot(Series::shared_x(&theta, &r), plot_par));
+
+        let traces = figure["data"].as_array().unwrap();
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[0]["type"], "scatterpolar");
+        assert_eq!(traces[0]["theta"], json!([0.0, 90.0, 180.0]));
+        assert_eq!(traces[1]["r"], json!([3.0, 2.0, 1.0]));
+        assert_eq!(traces[1]["name"], "b");
+        let polar = &figure["layout"]["polar"];
+        assert_eq!(polar["radialaxis"]["type"], "log");
+        assert_eq!(polar["radialaxis"]["range"], json!([0.0, 1.0]));
+        assert_eq!(polar["radialaxis"]["title"]["text"], "y");
+        assert_eq!(polar["angularaxis"]["direction"], "counterclockwise");
+        assert!(polar["angularaxis"].get("type").is_none());
+    }
+}
@@ -0,0 +1,209 @@
+// Visual-preserving downsampling of long series before they are sent to plotly.
+// Only the rendered traces are reduced, the data passed in is left untouched.
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use crate::plot::PlotPar;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Downsample {
+    Off,
+    // Largest-Triangle-Three-Buckets, LTTB_POINTS_PER_PIXEL points per pixel of width
+    Lttb,
+    // first, smallest, largest and last point in every pixel column of width
+    MinMax,
+}
+
+// "off", "lttb" or "min-max"
+impl FromStr for Downsample {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Downsample, String> {
+        Ok(match s {
+            "off" => Downsample::Off,
+            "lttb" => Downsample::Lttb,
+            "min-max" => Downsample::MinMax,
+            _ => return Err(format!("unknown downsampling {:?}", s)),
+        })
+    }
+}
+
+const LTTB_POINTS_PER_PIXEL: usize = 2;
+
+type Reducer = fn(&[f64], &[f64], usize) -> (Vec<f64>, Vec<f64>);
+
+// Series reduced according to plot_par.downsample and plot_par.width, NaN gaps are kept
+pub fn for_plot<'a>(x: &'a [f64], y: &'a [f64], plot_par: &PlotPar) -> (Cow<'a, [f64]>, Cow<'a, [f64]>) {
+    let width = plot_par.width.max(1);
+    let reduced = match plot_par.downsample {
+        Downsample::Off => None,
+        Downsample::Lttb => {
+            let n_out = LTTB_POINTS_PER_PIXEL * width;
+            if x.len() <= n_out { None } else { Some(by_segments(x, y, n_out, lttb)) }
+        },
+        Downsample::MinMax => {
+            if x.len() <= 4 * width { None } else { Some(by_segments(x, y, width, min_max)) }
+        },
+    };
+    match reduced {
+        Some((x, y)) => (Cow::Owned(x), Cow::Owned(y)),
+        None => (Cow::Borrowed(x), Cow::Borrowed(y)),
+    }
+}
+
+// Applies f to every run of finite points, giving each run a share of n proportional to its length
+fn by_segments(x: &[f64], y: &[f64], n: usize, f: Reducer) -> (Vec<f64>, Vec<f64>) {
+    let len = x.len().min(y.len());
+    let mut out = (Vec::new(), Vec::new());
+    let mut start = 0;
+    while start < len {
+        let finite = |i: usize| x[i].is_finite() && y[i].is_finite();
+        if !finite(start) {
+            // a single NaN keeps the line broken
+            if out.1.last().map(|v: &f64| !v.is_nan()).unwrap_or(false) {
+                out.0.push(x[start]);
+                out.1.push(f64::NAN);
+            }
+            start += 1;
+            continue;
+        }
+        let end = (start..len).find(|&i| !finite(i)).unwrap_or(len);
+        let share = ((n as f64) * ((end - start) as f64) / (len as f64)).ceil() as usize;
+        let (sx, sy) = f(&x[start..end], &y[start..end], share.max(3));
+        out.0.extend(sx);
+        out.1.extend(sy);
+        start = end;
+    }
+    out
+}
+
+pub fn lttb(x: &[f64], y: &[f64], n_out: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = x.len();
+    if n_out >= n || n_out < 3 {
+        return (x.to_vec(), y.to_vec());
+    }
+    let bucket = (n - 2) as f64 / (n_out - 2) as f64;
+    let mut xs = Vec::with_capacity(n_out);
+    let mut ys = Vec::with_capacity(n_out);
+    xs.push(x[0]);
+    ys.push(y[0]);
+    let mut a = 0;
+    for i in 0..n_out - 2 {
+        let start = (i as f64 * bucket) as usize + 1;
+        let end = (((i + 1) as f64 * bucket) as usize + 1).min(n - 1);
+        // average of the next bucket, or the last point for the last bucket
+        let next_start = end;
+        let next_end = (((i + 2) as f64 * bucket) as usize + 1).min(n);
+        let count = (next_end - next_start).max(1) as f64;
+        let (avg_x, avg_y) = if next_end > next_start {
+            (x[next_start..next_end].iter().sum::<f64>() / count, y[next_start..next_end].iter().sum::<f64>() / count)
+        } else {
+            (x[n - 1], y[n - 1])
+        };
+        let mut best = start;
+        let mut best_area = -1.0;
+        for j in start..end.max(start + 1) {
+            let area = ((x[a] - avg_x) * (y[j] - y[a]) - (x[a] - x[j]) * (avg_y - y[a])).abs();
+            if area > best_area {
+                best_area = area;
+                best = j;
+            }
+        }
+        xs.push(x[best]);
+        ys.push(y[best]);
+        a = best;
+    }
+    xs.push(x[n - 1]);
+    ys.push(y[n - 1]);
+    (xs, ys)
+}
+
+// First, smallest, largest and last point of every bucket, in their original order.
+// Buckets split the x range evenly when x is sorted, otherwise the indices.
+pub fn min_max(x: &[f64], y: &[f64], buckets: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = x.len();
+    if n <= 4 * buckets || buckets == 0 {
+        return (x.to_vec(), y.to_vec());
+    }
+    let sorted = x.windows(2).all(|w| w[0] <= w[1]);
+    let bucket_of = |i: usize| -> usize {
+        if sorted && x[n - 1] > x[0] {
+            (((x[i] - x[0]) / (x[n - 1] - x[0]) * buckets as f64) as usize).min(buckets - 1)
+        } else {
+            i * buckets / n
+        }
+    };
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let mut start = 0;
+    while start < n {
+        let b = bucket_of(start);
+        let mut end = start + 1;
+        while end < n && bucket_of(end) == b {
+            end += 1;
+        }
+        let (mut lo, mut hi) = (start, start);
+        for i in start..end {
+            if y[i] < y[lo] {
+                lo = i;
+            }
+            if y[i] > y[hi] {
+                hi = i;
+            }
+        }
+        let mut keep = vec![start, lo, hi, end - 1];
+        keep.sort_unstable();
+        keep.dedup();
+        for i in keep {
+            xs.push(x[i]);
+            ys.push(y[i]);
+        }
+        start = end;
+    }
+    (xs, ys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a slow sine with a spike at 500
+    fn signal() -> (Vec<f64>, Vec<f64>) {
+        let x: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let y = x.iter().map(|&x| if x == 500.0 { 10.0 } else { (x / 100.0).sin() }).collect();
+        (x, y)
+    }
+
+    #[test]
+    fn lttb_keeps_ends_and_peaks() {
+        let (x, y) = signal();
+        let (xs, ys) = lttb(&x, &y, 50);
+        assert_eq!(xs.len(), 50);
+        assert_eq!((xs[0], xs[49]), (0.0, 999.0));
+        assert!(xs.windows(2).all(|w| w[0] < w[1]));
+        assert!(ys.contains(&10.0));
+        assert_eq!(lttb(&x[..10], &y[..10], 50).0, x[..10].to_vec());
+    }
+
+    #[test]
+    fn min_max_keeps_extremes() {
+        let (x, y) = signal();
+        let (xs, ys) = min_max(&x, &y, 20);
+        assert!(xs.len() <= 80 && xs.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!((xs[0], *xs.last().unwrap()), (0.0, 999.0));
+        let lowest = y.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert!(ys.contains(&10.0) && ys.contains(&lowest));
+    }
+
+    #[test]
+    fn gaps_stay_broken() {
+        let (x, mut y) = signal();
+        y[300] = f64::NAN;
+        let mut plot_par = PlotPar::new(20, 20, "x", "y", "", "gaps", Vec::new());
+        plot_par.downsample = Downsample::Lttb;
+        let (xs, ys) = for_plot(&x, &y, &plot_par);
+        assert!(xs.len() < 100);
+        assert_eq!(ys.iter().filter(|v| v.is_nan()).count(), 1);
+        plot_par.downsample = Downsample::Off;
+        assert!(matches!(for_plot(&x, &y, &plot_par).0, Cow::Borrowed(_)));
+    }
+}
@@ -1,30 +1,224 @@
-// Saving data to files
-use std::fs::File;
+// Saving data to files. Text files named *.gz or *.zst are compressed and decompressed on the fly.
 use std::io::Write;
-use std::path::PathBuf;
-
-pub fn save_columns_to_file(vecs: &Vec<Vec<f64>>, dir_name: &str, file_name: &str) {
-    let try_create_dir = std::fs::create_dir(dir_name);
-    match try_create_dir {
-        Ok(create_dir) => {create_dir},
-        Err(_) => {},
-    };
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+
+use crate::compression::Writer;
+use crate::series::IntoSeries;
+
+#[cfg(feature = "arrow")]
+pub use crate::columnar::{plot_metadata, read_arrow, save_columns_to_arrow, Table};
+pub use crate::fortran::{read_fortran_records, Endian, FortranOptions, RecordMarker, RecordType};
+pub use crate::npy::{read_npy, read_npz, save_columns_to_npy, save_columns_to_npz};
+pub use crate::tokenizer::{parse_number, read_columns_with, DecimalMark, Delimiter, ParseError, ParseOptions};
+
+// a ColumnWriter flushes at the first row written this long after its last flush
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// every flush of a compressed file starts a new gzip member or zstd frame, so it waits for
+// this many bytes of rows, or for CHECKPOINT_INTERVAL when rows come slowly
+const CHECKPOINT_BYTES: usize = 64 * 1024;
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+// Writes the columns of Series::columns, shorter columns padded with NAN
+pub fn save_columns_to_file<'a, S: IntoSeries<'a>>(series: S, dir_name: &str, file_name: &str) {
+    let series = series.into_series();
+    write_columns(&series.columns(), None, dir_name, file_name);
+}
+
+// Same as save_columns_to_file with a "# name1 name2 ..." header line
+pub fn save_named_columns_to_file<'a, S: IntoSeries<'a>>(series: S, names: &[&str], dir_name: &str, file_name: &str) {
+    let series = series.into_series();
+    write_columns(&series.columns(), Some(names), dir_name, file_name);
+}
+
+fn write_columns(vecs: &[&[f64]], header: Option<&[&str]>, dir_name: &str, file_name: &str) {
+    let _ = std::fs::create_dir(dir_name);
     let file_path: PathBuf = [dir_name, file_name].iter().collect();
-    let mut my_file = File::create(file_path).expect("Error creating file");
-    let vec_num = vecs.len();
-    let mut el_nums = Vec::new();
-    for i in 0..vec_num {
-        el_nums.push(vecs[i].len())
+    let mut my_file = Writer::create(&file_path).expect("Error creating file");
+    if let Some(names) = header {
+        writeln!(my_file, "# {}", names.join(" ")).expect("Can't write header to file");
     }
+    let el_nums: Vec<usize> = vecs.iter().map(|v| v.len()).collect();
     let num = el_nums.iter().max().unwrap().to_owned();
     for j in 0..num {
-        for i in 0..vec_num {
-            if el_nums[i] > j {
-                write!(my_file, "{:.6} ", vecs[i][j]).expect(&format!("Can't write line {} to file", j));
+        for (vec, &el_num) in vecs.iter().zip(el_nums.iter()) {
+            if el_num > j {
+                write!(my_file, "{:.6} ", vec[j]).unwrap_or_else(|_| panic!("Can't write line {} to file", j));
             } else {
-                write!(my_file, "{} ", "NAN").expect(&format!("Can't write line {} to file", j));
+                write!(my_file, "NAN ").unwrap_or_else(|_| panic!("Can't write line {} to file", j));
+            }
+        }
+        writeln!(my_file).unwrap_or_else(|_| panic!("Can't write line {} to file", j));
+    }
+    my_file.finish().unwrap_or_else(|_| panic!("Can't write {:?}", file_path));
+}
+
+// Reads a file written by save_columns_to_file, or any text data the default ParseOptions
+// handle: the header names, if any, and the columns. Panics with the position of a malformed token.
+pub fn read_columns_from_file<P: AsRef<Path>>(path: P) -> (Vec<String>, Vec<Vec<f64>>) {
+    let path = path.as_ref();
+    read_columns_with(path, &ParseOptions::new()).unwrap_or_else(|e| panic!("Error in {:?}: {}", path, e))
+}
+
+// Writes rows as they are produced, in the format of save_columns_to_file.
+// Rows are flushed at the next write_row after FLUSH_INTERVAL, so after a crash only the last
+// moment is lost and read_columns_from_file can read and plot the file while the run is still
+// going. Compressed files are flushed less often, see CHECKPOINT_BYTES. Nothing is flushed
+// between rows: a run that pauses for long between rows calls flush after a row to make it
+// visible at once. The file is finished when the writer is dropped, or with finish to see errors.
+pub struct ColumnWriter {
+    writer: Option<Writer>,
+    path: PathBuf,
+    columns: Option<usize>,
+    last_flush: Instant,
+    // bytes of rows written since the last flush
+    unflushed: usize,
+    line: String,
+}
+
+impl ColumnWriter {
+    // With a header every row must have one value per name
+    pub fn create(dir_name: &str, file_name: &str, header: Option<&[&str]>) -> ColumnWriter {
+        let _ = std::fs::create_dir(dir_name);
+        let path: PathBuf = [dir_name, file_name].iter().collect();
+        let mut writer = Writer::create(&path).expect("Error creating file");
+        if let Some(names) = header {
+            writeln!(writer, "# {}", names.join(" ")).expect("Can't write header to file");
+        }
+        let mut column_writer = ColumnWriter {
+            writer: Some(writer),
+            path,
+            columns: header.map(|names| names.len()),
+            last_flush: Instant::now(),
+            unflushed: 0,
+            line: String::new(),
+        };
+        column_writer.flush();
+        column_writer
+    }
+
+    pub fn write_row(&mut self, row: &[f64]) {
+        let columns = *self.columns.get_or_insert(row.len());
+        if row.len() != columns {
+            panic!("Got a row of {} values for {} columns in {:?}", row.len(), columns, self.path);
+        }
+        // one write per row, so that the file never ends inside a row
+        self.line.clear();
+        for value in row {
+            self.line.push_str(&format!("{:.6} ", value));
+        }
+        self.line.push('\n');
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_all(self.line.as_bytes()).unwrap_or_else(|_| panic!("Can't write row to {:?}", self.path));
+        self.unflushed += self.line.len();
+        let due = match writer {
+            Writer::Plain(_) => self.last_flush.elapsed() >= FLUSH_INTERVAL,
+            _ => (self.last_flush.elapsed() >= FLUSH_INTERVAL && self.unflushed >= CHECKPOINT_BYTES)
+                || self.last_flush.elapsed() >= CHECKPOINT_INTERVAL,
+        };
+        if due {
+            self.flush();
+        }
+    }
+
+    // Everything written so far to the file, readable as a finished file even when compressed
+    pub fn flush(&mut self) {
+        let writer = self.writer.take().unwrap();
+        self.writer = Some(writer.checkpoint().unwrap_or_else(|_| panic!("Can't flush {:?}", self.path)));
+        self.last_flush = Instant::now();
+        self.unflushed = 0;
+    }
+
+    pub fn finish(mut self) {
+        let writer = self.writer.take().unwrap();
+        writer.finish().unwrap_or_else(|_| panic!("Can't write {:?}", self.path));
+    }
+}
+
+impl Drop for ColumnWriter {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("file-{}-{}", name, std::process::id()))
+    }
+
+    // Rows as read_columns_with reads them, the unfinished end of a compressed file left out
+    fn rows(path: &Path) -> usize {
+        let (_, columns) = read_columns_with(path, &ParseOptions::new()).unwrap();
+        columns.first().map(|c| c.len()).unwrap_or(0)
+    }
+
+    #[test]
+    fn plain_output() {
+        let dir = dir("plain");
+        let mut writer = ColumnWriter::create(dir.to_str().unwrap(), "a.dat", None);
+        writer.write_row(&[1.0, -0.5]);
+        writer.write_row(&[2.0, f64::NAN]);
+        writer.finish();
+        let text = std::fs::read_to_string(dir.join("a.dat")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(text, "1.000000 -0.500000 \n2.000000 NaN \n");
+    }
+
+    #[test]
+    fn header() {
+        let dir = dir("header");
+        let mut writer = ColumnWriter::create(dir.to_str().unwrap(), "a.dat.gz", Some(&["t", "v"]));
+        writer.write_row(&[1.0, 2.0]);
+        drop(writer);
+        let (names, columns) = read_columns_from_file(dir.join("a.dat.gz"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((names, columns), (vec!["t".to_string(), "v".to_string()], vec![vec![1.0], vec![2.0]]));
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let dir = dir("drop");
+        for file_name in ["a.dat", "a.dat.gz", "a.dat.zst"] {
+            let path = dir.join(file_name);
+            let mut writer = ColumnWriter::create(dir.to_str().unwrap(), file_name, None);
+            for i in 0..100 {
+                writer.write_row(&[i as f64]);
+            }
+            drop(writer);
+            assert_eq!(rows(&path), 100, "{}", file_name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checkpoints() {
+        let dir = dir("checkpoints");
+        let row = [0.0; 8];
+        let row_bytes = "0.000000 ".len() * row.len() + 1;
+        for file_name in ["a.dat", "a.dat.gz", "a.dat.zst"] {
+            let path = dir.join(file_name);
+            let mut writer = ColumnWriter::create(dir.to_str().unwrap(), file_name, None);
+            writer.last_flush -= FLUSH_INTERVAL;
+            writer.write_row(&row);
+            // a plain file is flushed after FLUSH_INTERVAL, a compressed one waits for CHECKPOINT_BYTES
+            let compressed = file_name != "a.dat";
+            assert_eq!(rows(&path), if compressed { 0 } else { 1 }, "{}", file_name);
+            for _ in 1..CHECKPOINT_BYTES / row_bytes {
+                writer.write_row(&row);
             }
+            writer.last_flush -= FLUSH_INTERVAL;
+            writer.write_row(&row);
+            assert_eq!(rows(&path), CHECKPOINT_BYTES / row_bytes + 1, "{}", file_name);
+            // or for CHECKPOINT_INTERVAL
+            writer.last_flush -= CHECKPOINT_INTERVAL;
+            writer.write_row(&row);
+            assert_eq!(rows(&path), CHECKPOINT_BYTES / row_bytes + 2, "{}", file_name);
         }
-        write!(my_file, "{}", "\n").expect(&format!("Can't write line {} to file", j));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
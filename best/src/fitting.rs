@@ -0,0 +1,287 @@
+// Least squares curve fitting with parameter uncertainties.
+// Weights are 1/σ² of every point. With weights the uncertainties follow from σ,
+// without them they are scaled by the reduced χ² of the fit.
+
+const LM_MAX_ITERATIONS: usize = 500;
+const LM_TOLERANCE: f64 = 1e-12;
+
+type Model = Box<dyn Fn(f64, &[f64]) -> f64>;
+
+pub struct Fit {
+    pub names: Vec<String>,
+    pub params: Vec<f64>,
+    pub errors: Vec<f64>,
+    pub chi2: f64,
+    pub dof: usize,
+    model: Model,
+}
+
+impl Fit {
+    pub fn eval(&self, x: f64) -> f64 {
+        (self.model)(x, &self.params)
+    }
+
+    // Fitted curve sampled at n points, to be passed to line_plot next to the data
+    pub fn curve(&self, x_range: [f64; 2], n: usize) -> (Vec<f64>, Vec<f64>) {
+        let n = n.max(2);
+        let dx = (x_range[1] - x_range[0]) / ((n - 1) as f64);
+        let x: Vec<f64> = (0..n).map(|i| x_range[0] + i as f64 * dx).collect();
+        let y = x.iter().map(|&x| self.eval(x)).collect();
+        (x, y)
+    }
+
+    // Legend text such as " a = 1.23 ± 0.05, b = (4.6 ± 0.2)×10<sup>-3</sup> "
+    pub fn legend(&self) -> String {
+        let params: Vec<String> = (0..self.params.len())
+            .map(|i| format!("{} = {}", self.names[i], format_uncertainty(self.params[i], self.errors[i])))
+            .collect();
+        format!(" {} ", params.join(", "))
+    }
+}
+
+// y = a + b*x
+pub fn fit_linear(x: &[f64], y: &[f64], weights: Option<&[f64]>) -> Result<Fit, String> {
+    let mut fit = fit_polynomial(x, y, 1, weights)?;
+    fit.names = vec!["a".to_string(), "b".to_string()];
+    Ok(fit)
+}
+
+// y = a0 + a1*x + ... + an*x^n; an error with fewer than degree + 1 distinct x values
+pub fn fit_polynomial(x: &[f64], y: &[f64], degree: usize, weights: Option<&[f64]>) -> Result<Fit, String> {
+    let model = |x: f64, p: &[f64]| p.iter().rev().fold(0.0, |acc, a| acc * x + a);
+    let jacobian: Vec<Vec<f64>> = x.iter().map(|&x| (0..=degree).map(|k| x.powi(k as i32)).collect()).collect();
+    let w = weights_or_ones(weights, x.len(), y.len())?;
+
+    let (a, b) = normal_equations(&jacobian, y, &w);
+    let cov = invert(a).ok_or_else(|| format!("Too few distinct x values for a polynomial of degree {}", degree))?;
+    let params: Vec<f64> = (0..=degree).map(|i| (0..=degree).map(|j| cov[i][j] * b[j]).sum()).collect();
+
+    Ok(finish(
+        (0..=degree).map(|k| format!("a{}", k)).collect(),
+        params, cov, (x, y, &w), weights.is_some(), Box::new(model),
+    ))
+}
+
+// y = A*exp(b*x), starting from a straight line fit of log(y)
+pub fn fit_exponential(x: &[f64], y: &[f64], weights: Option<&[f64]>) -> Result<Fit, String> {
+    let (xp, lnyp): (Vec<f64>, Vec<f64>) = x.iter().zip(y.iter())
+        .filter(|(_, y)| **y > 0.0)
+        .map(|(x, y)| (*x, y.ln()))
+        .unzip();
+    let initial = match fit_polynomial(&xp, &lnyp, 1, None) {
+        Ok(line) => vec![line.params[0].exp(), line.params[1]],
+        Err(_) => vec![1.0, 0.0],
+    };
+    let mut fit = fit_nonlinear(|x, p| p[0] * (p[1] * x).exp(), x, y, weights, &initial)?;
+    fit.names = vec!["A".to_string(), "b".to_string()];
+    Ok(fit)
+}
+
+// Levenberg–Marquardt fit of an arbitrary model y = f(x, p) starting from initial;
+// parameters it can't determine get NaN errors
+pub fn fit_nonlinear<F>(model: F, x: &[f64], y: &[f64], weights: Option<&[f64]>, initial: &[f64]) -> Result<Fit, String>
+where
+    F: Fn(f64, &[f64]) -> f64 + 'static,
+{
+    let w = weights_or_ones(weights, x.len(), y.len())?;
+    let np = initial.len();
+    let chi2_of = |p: &[f64]| -> f64 {
+        x.iter().zip(y.iter()).zip(w.iter()).map(|((&x, &y), &w)| w * (y - model(x, p)).powi(2)).sum()
+    };
+
+    let mut p = initial.to_vec();
+    let mut chi2 = chi2_of(&p);
+    let mut lambda = 1e-3;
+    for _ in 0..LM_MAX_ITERATIONS {
+        let jacobian = numerical_jacobian(&model, x, &p);
+        let residuals: Vec<f64> = x.iter().zip(y.iter()).map(|(&x, &y)| y - model(x, &p)).collect();
+        let (a, b) = normal_equations(&jacobian, &residuals, &w);
+
+        let mut improved = false;
+        while lambda < 1e16 {
+            let mut damped = a.clone();
+            for i in 0..np {
+                damped[i][i] += lambda * a[i][i].max(f64::EPSILON);
+            }
+            let step = match invert(damped) {
+                Some(inv) => (0..np).map(|i| (0..np).map(|j| inv[i][j] * b[j]).sum::<f64>()).collect::<Vec<f64>>(),
+                None => break,
+            };
+            let trial: Vec<f64> = p.iter().zip(step.iter()).map(|(p, s)| p + s).collect();
+            let trial_chi2 = chi2_of(&trial);
+            if trial_chi2.is_finite() && trial_chi2 <= chi2 {
+                let converged = chi2 - trial_chi2 <= LM_TOLERANCE * chi2.max(f64::MIN_POSITIVE);
+                p = trial;
+                chi2 = trial_chi2;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = !converged;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    let jacobian = numerical_jacobian(&model, x, &p);
+    let (a, _) = normal_equations(&jacobian, y, &w);
+    let cov = invert(a).unwrap_or_else(|| vec![vec![f64::NAN; np]; np]);
+
+    Ok(finish(
+        (0..np).map(|k| format!("p{}", k)).collect(),
+        p, cov, (x, y, &w), weights.is_some(), Box::new(model),
+    ))
+}
+
+fn finish(names: Vec<String>, params: Vec<f64>, cov: Vec<Vec<f64>>, data: (&[f64], &[f64], &[f64]), weighted: bool, model: Model) -> Fit {
+    let (x, y, w) = data;
+    let chi2: f64 = x.iter().zip(y.iter()).zip(w.iter()).map(|((&x, &y), &w)| w * (y - model(x, &params)).powi(2)).sum();
+    let dof = x.len().saturating_sub(params.len());
+    let scale = if weighted || dof == 0 { 1.0 } else { chi2 / dof as f64 };
+    let errors = (0..params.len()).map(|i| (cov[i][i] * scale).sqrt()).collect();
+    Fit { names, params, errors, chi2, dof, model }
+}
+
+fn weights_or_ones(weights: Option<&[f64]>, n: usize, n_y: usize) -> Result<Vec<f64>, String> {
+    if n_y != n {
+        return Err(format!("Got {} y values for {} x values", n_y, n));
+    }
+    match weights {
+        Some(w) if w.len() != n => Err(format!("Got {} weights for {} points", w.len(), n)),
+        Some(w) => Ok(w.to_vec()),
+        None => Ok(vec![1.0; n]),
+    }
+}
+
+// JᵀWJ and JᵀWr
+fn normal_equations(jacobian: &[Vec<f64>], r: &[f64], w: &[f64]) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let np = jacobian.first().map(|row| row.len()).unwrap_or(0);
+    let mut a = vec![vec![0.0; np]; np];
+    let mut b = vec![0.0; np];
+    for (k, row) in jacobian.iter().enumerate() {
+        for i in 0..np {
+            b[i] += w[k] * row[i] * r[k];
+            for j in 0..np {
+                a[i][j] += w[k] * row[i] * row[j];
+            }
+        }
+    }
+    (a, b)
+}
+
+fn numerical_jacobian<F: Fn(f64, &[f64]) -> f64>(model: &F, x: &[f64], p: &[f64]) -> Vec<Vec<f64>> {
+    let mut shifted = p.to_vec();
+    x.iter().map(|&x| {
+        (0..p.len()).map(|i| {
+            let h = f64::EPSILON.sqrt() * p[i].abs().max(1.0);
+            shifted[i] = p[i] + h;
+            let up = model(x, &shifted);
+            shifted[i] = p[i] - h;
+            let down = model(x, &shifted);
+            shifted[i] = p[i];
+            (up - down) / (2.0 * h)
+        }).collect()
+    }).collect()
+}
+
+// Gauss-Jordan elimination with partial pivoting
+pub(crate) fn invert(mut a: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    let mut inv: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col] == 0.0 || !a[pivot][col].is_finite() {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        let d = a[col][col];
+        for j in 0..n {
+            a[col][j] /= d;
+            inv[col][j] /= d;
+        }
+        for i in 0..n {
+            if i != col {
+                let f = a[i][col];
+                for j in 0..n {
+                    a[i][j] -= f * a[col][j];
+                    inv[i][j] -= f * inv[col][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+// Value and uncertainty rounded to two significant figures of the uncertainty,
+// with a common power of ten for very large or small values or uncertainties
+pub fn format_uncertainty(value: f64, error: f64) -> String {
+    if !error.is_finite() || error <= 0.0 {
+        return format!("{:.4}", value);
+    }
+    // decimals of two significant figures, counted again after rounding as 0.0996 becomes 0.10
+    let mut digits = 1 - error.log10().floor() as i32;
+    if (error * 10f64.powi(digits)).round() >= 100.0 {
+        digits -= 1;
+    }
+    // errors of 100 and more are rounded to tens, hundreds, ... as in 1230 ± 570
+    let step = 10f64.powi(-digits);
+    let (v, e) = ((value / step).round() * step, (error / step).round() * step);
+    let magnitude = v.abs().max(e).log10().floor() as i32;
+    let exponent = if !(-3..4).contains(&magnitude) { magnitude } else { 0 };
+    let scale = 10f64.powi(exponent);
+    let decimals = (digits + exponent).max(0) as usize;
+    if exponent == 0 {
+        format!("{:.*} ± {:.*}", decimals, v, decimals, e)
+    } else {
+        format!("({:.*} ± {:.*})×10<sup>{}</sup>", decimals, v / scale, decimals, e / scale, exponent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uncertainties() {
+        assert_eq!(format_uncertainty(1.23456, 0.0123), "1.235 ± 0.012");
+        assert_eq!(format_uncertainty(12.345, 1.5), "12.3 ± 1.5");
+        assert_eq!(format_uncertainty(1234.0, 567.0), "1230 ± 570");
+        assert_eq!(format_uncertainty(3.0, 0.0), "3.0000");
+        assert_eq!(format_uncertainty(3.0, f64::NAN), "3.0000");
+        assert_eq!(format_uncertainty(0.000456, 0.000012), "(4.56 ± 0.12)×10<sup>-4</sup>");
+        assert_eq!(format_uncertainty(123456.0, 7890.0), "(1.235 ± 0.079)×10<sup>5</sup>");
+        // the power of ten of a larger error, and an error that rounds up to another figure
+        assert_eq!(format_uncertainty(1e-5, 2e-3), "0.0000 ± 0.0020");
+        assert_eq!(format_uncertainty(1e-6, 2e-5), "(0.1 ± 2.0)×10<sup>-5</sup>");
+        assert_eq!(format_uncertainty(0.0996, 0.0996), "0.10 ± 0.10");
+        assert_eq!(format_uncertainty(9.96, 0.0996), "9.96 ± 0.10");
+    }
+
+    #[test]
+    fn straight_line() {
+        let x = [0.0, 1.0, 2.0, 3.0];
+        let y = [1.0, 3.1, 4.9, 7.0];
+        let fit = fit_linear(&x, &y, None).unwrap();
+        assert!((fit.params[0] - 1.03).abs() < 1e-9 && (fit.params[1] - 1.98).abs() < 1e-9);
+        assert_eq!(fit.dof, 2);
+        assert!(fit.errors.iter().all(|e| *e > 0.0));
+    }
+
+    #[test]
+    fn exponential() {
+        let x: Vec<f64> = (0..10).map(|i| i as f64 * 0.3).collect();
+        let y: Vec<f64> = x.iter().map(|x| 2.5 * (-0.7 * x).exp()).collect();
+        let fit = fit_exponential(&x, &y, None).unwrap();
+        assert!((fit.params[0] - 2.5).abs() < 1e-6 && (fit.params[1] + 0.7).abs() < 1e-6);
+        assert!((fit.eval(1.0) - 2.5 * (-0.7f64).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn singular_and_mismatched() {
+        assert!(fit_polynomial(&[1.0, 1.0], &[1.0, 2.0], 1, None).is_err());
+        assert!(fit_linear(&[0.0, 1.0, 2.0], &[1.0, 2.0, 3.0], Some(&[1.0, 1.0])).is_err());
+        assert!(fit_nonlinear(|x, p| p[0] * x, &[0.0, 1.0], &[1.0], None, &[1.0]).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+// Fonts for offline html: the TrueType and OpenType files of a family in the usual font
+// directories, embedded as @font-face rules when the font's licensing allows it. That is
+// fsType in the OS/2 table; fonts with restricted license embedding are left out.
+// Pdf captions with characters that Helvetica hasn't got embed a TrueType font found the same way.
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use directories::BaseDirs;
+
+// CSS generic families, which every browser has
+const GENERIC: [&str; 8] = ["serif", "sans-serif", "monospace", "cursive", "fantasy", "system-ui", "math", "emoji"];
+
+// families tried first for pdf captions, the others when none of them has all the characters
+const CAPTION_FAMILIES: [&str; 5] = ["dejavu sans", "liberation sans", "arial", "noto sans", "freesans"];
+
+struct FontInfo {
+    // typographic family, e.g. "DejaVu Serif" for DejaVuSerifCondensed-Bold.ttf
+    family: String,
+    // e.g. "DejaVuSerifCondensed-Bold"
+    postscript: String,
+    weight: u16,
+    width: u16,
+    italic: bool,
+    embeddable: bool,
+}
+
+// @font-face rules with the fonts as data URLs for the families of font_family, a CSS
+// list such as "Palatino Linotype, Book Antiqua, serif"; empty when none is found
+pub(crate) fn font_faces(font_family: &str) -> String {
+    let families: Vec<String> = font_family.split(',')
+        .map(|f| f.trim().trim_matches(|c| c == '"' || c == '\'').to_lowercase())
+        .filter(|f| !f.is_empty() && !GENERIC.contains(&f.as_str()))
+        .collect();
+    if families.is_empty() {
+        return String::new();
+    }
+
+    let mut css = String::new();
+    for path in font_files() {
+        // only the fonts of the families are read as a whole
+        let info = match FontFile::open(&path).and_then(|mut font| font_info(&mut font)) {
+            Some(info) if info.embeddable && families.contains(&info.family.to_lowercase()) => info,
+            _ => continue,
+        };
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let (mime, format) = if data.starts_with(b"OTTO") { ("font/otf", "opentype") } else { ("font/ttf", "truetype") };
+        let stretch = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0][info.width.clamp(1, 9) as usize - 1];
+        css.push_str(&format!(
+            "@font-face {{ font-family: \"{}\"; font-weight: {}; font-style: {}; font-stretch: {}%; src: url(data:{};base64,{}) format(\"{}\"); }}\n",
+            info.family, info.weight, if info.italic { "italic" } else { "normal" }, stretch, mime, base64::encode(&data), format,
+        ));
+    }
+    css
+}
+
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/fonts"),
+        PathBuf::from("/usr/local/share/fonts"),
+        PathBuf::from("/Library/Fonts"),
+        PathBuf::from("/System/Library/Fonts"),
+    ];
+    if let Some(base) = BaseDirs::new() {
+        let home = base.home_dir();
+        dirs.extend([home.join(".fonts"), home.join(".local/share/fonts"), home.join("Library/Fonts")]);
+        dirs.push(base.data_local_dir().join("Microsoft/Windows/Fonts"));
+    }
+    if let Some(windows) = std::env::var_os("WINDIR") {
+        dirs.push(PathBuf::from(windows).join("Fonts"));
+    }
+    dirs
+}
+
+// The .ttf and .otf files in the font directories, sorted
+fn font_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    for dir in font_dirs() {
+        collect_font_files(&dir, &mut files, &mut visited);
+    }
+    files.sort();
+    files
+}
+
+// visited holds the canonical paths of the directories already searched, so that
+// symbolic links to a directory that contains them don't make it endless
+fn collect_font_files(dir: &Path, files: &mut Vec<PathBuf>, visited: &mut HashSet<PathBuf>) {
+    match fs::canonicalize(dir) {
+        Ok(canonical) if !visited.contains(&canonical) => visited.insert(canonical),
+        _ => return,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        if path.is_dir() {
+            collect_font_files(&path, files, visited);
+        } else if matches!(extension.as_deref(), Some("ttf") | Some("otf")) {
+            files.push(path);
+        }
+    }
+}
+
+// A font file with its table directory, whose tables are read one at a time
+struct FontFile {
+    file: File,
+    // the first 12 bytes and the table records
+    directory: Vec<u8>,
+}
+
+impl FontFile {
+    fn open(path: &Path) -> Option<FontFile> {
+        let mut file = File::open(path).ok()?;
+        let mut directory = vec![0; 12];
+        file.read_exact(&mut directory).ok()?;
+        if !matches!(directory[..4], [0, 1, 0, 0] | [b'O', b'T', b'T', b'O'] | [b't', b'r', b'u', b'e']) {
+            return None;
+        }
+        let records = 16 * u16_at(&directory, 4)? as u64;
+        (&mut file).take(records).read_to_end(&mut directory).ok()?;
+        Some(FontFile { file, directory })
+    }
+
+    // None when the font has no such table or the file ends inside it
+    fn table(&mut self, tag: &[u8]) -> Option<Vec<u8>> {
+        let (offset, length) = table(&self.directory, tag)?;
+        self.file.seek(SeekFrom::Start(offset as u64)).ok()?;
+        let mut data = Vec::new();
+        (&mut self.file).take(length as u64).read_to_end(&mut data).ok()?;
+        (data.len() == length).then_some(data)
+    }
+}
+
+// A regular upright TrueType font, the whole file, with its glyphs and advance widths
+pub(crate) struct TrueType {
+    pub(crate) name: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) units_per_em: f64,
+    // xMin, yMin, xMax, yMax of all glyphs in font units, and ascender and descender
+    pub(crate) bbox: [f64; 4],
+    pub(crate) ascent: f64,
+    pub(crate) descent: f64,
+    // offset and format, 4 or 12, of the Unicode subtable of cmap
+    cmap: (usize, u16),
+    // offset of hmtx and the number of advance widths in it
+    metrics: (usize, usize),
+}
+
+impl TrueType {
+    fn parse(name: String, data: Vec<u8>) -> Option<TrueType> {
+        let i16_at = |offset: usize| u16_at(&data, offset).map(|v| v as i16 as f64);
+        let (head, _) = table(&data, b"head")?;
+        let (hhea, _) = table(&data, b"hhea")?;
+        let (hmtx, _) = table(&data, b"hmtx")?;
+        let (cmap, _) = table(&data, b"cmap")?;
+        let metrics = (hmtx, u16_at(&data, hhea + 34)? as usize);
+
+        // full Unicode (3, 10) before the Basic Multilingual Plane
+        let mut subtables = Vec::new();
+        for k in 0..u16_at(&data, cmap + 2)? as usize {
+            let record = cmap + 4 + 8 * k;
+            let (platform, encoding) = (u16_at(&data, record)?, u16_at(&data, record + 2)?);
+            let offset = cmap + u32_at(&data, record + 4)? as usize;
+            let format = u16_at(&data, offset)?;
+            let rank = match (platform, encoding, format) {
+                (3, 10, 12) | (0, _, 12) => 0,
+                (3, 1, 4) | (0, _, 4) => 1,
+                _ => continue,
+            };
+            subtables.push((rank, (offset, format)));
+        }
+        let cmap = subtables.into_iter().min_by_key(|(rank, _)| *rank)?.1;
+        if metrics.1 == 0 {
+            return None;
+        }
+
+        Some(TrueType {
+            units_per_em: u16_at(&data, head + 18)? as f64,
+            bbox: [i16_at(head + 36)?, i16_at(head + 38)?, i16_at(head + 40)?, i16_at(head + 42)?],
+            ascent: i16_at(hhea + 4)?,
+            descent: i16_at(hhea + 6)?,
+            name,
+            cmap,
+            metrics,
+            data,
+        })
+    }
+
+    // None when the font has no glyph for c
+    pub(crate) fn glyph(&self, c: char) -> Option<u16> {
+        let data = &self.data;
+        let (offset, format) = self.cmap;
+        let c = c as u32;
+        let glyph = if format == 12 {
+            (0..u32_at(data, offset + 12)? as usize).map(|k| offset + 16 + 12 * k).find_map(|group| {
+                let (start, end, first) = (u32_at(data, group)?, u32_at(data, group + 4)?, u32_at(data, group + 8)?);
+                (start..=end).contains(&c).then(|| first + c - start)
+            })?
+        } else {
+            let segments = u16_at(data, offset + 6)? as usize / 2;
+            let ends = offset + 14;
+            let (starts, deltas, ranges) = (ends + 2 * segments + 2, ends + 4 * segments + 2, ends + 6 * segments + 2);
+            let k = (0..segments).find(|&k| u16_at(data, ends + 2 * k).map(|end| end as u32 >= c).unwrap_or(false))?;
+            let start = u16_at(data, starts + 2 * k)? as u32;
+            let delta = u16_at(data, deltas + 2 * k)? as u32;
+            let range = u16_at(data, ranges + 2 * k)? as usize;
+            if c < start {
+                return None;
+            } else if range == 0 {
+                (c + delta) & 0xffff
+            } else {
+                match u16_at(data, ranges + 2 * k + range + 2 * (c - start) as usize)? {
+                    0 => return None,
+                    glyph => (glyph as u32 + delta) & 0xffff,
+                }
+            }
+        };
+        u16::try_from(glyph).ok().filter(|&glyph| glyph != 0)
+    }
+
+    // in font units
+    pub(crate) fn advance(&self, glyph: u16) -> f64 {
+        let (hmtx, count) = self.metrics;
+        u16_at(&self.data, hmtx + 4 * (glyph as usize).min(count - 1)).unwrap_or(0) as f64
+    }
+}
+
+// An embeddable regular TrueType font with a glyph for every character of text, one of
+// CAPTION_FAMILIES if they have them; None when no font has all of them
+pub(crate) fn caption_font(text: &str) -> Option<TrueType> {
+    let mut best: Option<(usize, TrueType)> = None;
+    for path in font_files() {
+        let info = match FontFile::open(&path) {
+            // CFF outlines of OpenType fonts are embedded differently
+            Some(font) if font.directory.starts_with(b"OTTO") => continue,
+            Some(mut font) => font_info(&mut font),
+            None => continue,
+        };
+        let info = match info {
+            Some(info) if info.embeddable && !info.italic && info.weight == 400 && info.width == 5 => info,
+            _ => continue,
+        };
+        let rank = CAPTION_FAMILIES.iter().position(|&f| f == info.family.to_lowercase()).unwrap_or(CAPTION_FAMILIES.len());
+        if best.as_ref().map(|(best, _)| rank >= *best).unwrap_or(false) {
+            continue;
+        }
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let font = match TrueType::parse(info.postscript, data) {
+            Some(font) if text.chars().filter(|c| !c.is_whitespace()).all(|c| font.glyph(c).is_some()) => font,
+            _ => continue,
+        };
+        best = Some((rank, font));
+        if rank == 0 {
+            break;
+        }
+    }
+    best.map(|(_, font)| font)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// Offset and length of a table of a font file
+fn table(data: &[u8], tag: &[u8]) -> Option<(usize, usize)> {
+    (0..u16_at(data, 4)? as usize)
+        .map(|k| 12 + 16 * k)
+        .find(|&record| data.get(record..record + 4) == Some(tag))
+        .and_then(|record| Some((u32_at(data, record + 8)? as usize, u32_at(data, record + 12)? as usize)))
+}
+
+// Family, style and embedding permission from the name and OS/2 tables of a font file
+fn font_info(font: &mut FontFile) -> Option<FontInfo> {
+    let os2 = font.table(b"OS/2")?;
+    if os2.len() < 64 {
+        return None;
+    }
+    let name = font.table(b"name")?;
+    let data = &name;
+    let u16_at = |offset: usize| u16_at(data, offset);
+    let fs_type = self::u16_at(&os2, 8)?;
+    // editable or preview and print embedding lift the restriction, bitmap only fonts have nothing to embed
+    let embeddable = (fs_type & 0x0002 == 0 || fs_type & 0x000c != 0) && fs_type & 0x0200 == 0;
+
+    // typographic family (name 16), or the family (name 1) when there is none
+    let count = u16_at(2)? as usize;
+    let strings = u16_at(4)? as usize;
+    let mut names: Vec<(u16, String)> = Vec::new();
+    for k in 0..count {
+        let record = 6 + 12 * k;
+        let (platform, language, id) = (u16_at(record)?, u16_at(record + 4)?, u16_at(record + 6)?);
+        let bytes = data.get(strings + u16_at(record + 10)? as usize..)?.get(..u16_at(record + 8)? as usize)?;
+        let text = match (platform, language) {
+            (0, _) | (3, 0x0409) => String::from_utf16_lossy(&bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect::<Vec<_>>()),
+            (1, 0) => bytes.iter().map(|&b| b as char).collect(),
+            _ => continue,
+        };
+        if id == 1 || id == 6 || id == 16 {
+            names.push((id, text));
+        }
+    }
+    let family = names.iter().find(|(id, _)| *id == 16).or_else(|| names.iter().find(|(id, _)| *id == 1))?.1.clone();
+    let postscript = names.iter().find(|(id, _)| *id == 6).map(|(_, name)| name.clone())
+        .unwrap_or_else(|| family.chars().filter(|c| c.is_ascii_alphanumeric()).collect());
+
+    Some(FontInfo {
+        family,
+        postscript,
+        weight: self::u16_at(&os2, 4)?,
+        width: self::u16_at(&os2, 6)?,
+        italic: self::u16_at(&os2, 62)? & 0x0001 != 0,
+        embeddable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A TrueType font with glyphs for 'A' and 'B' only, named family, with the given fsType
+    fn fixture(family: &str, fs_type: u16) -> Vec<u8> {
+        let be = |values: &[u16]| values.iter().flat_map(|v| v.to_be_bytes()).collect::<Vec<u8>>();
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        head[36..44].copy_from_slice(&be(&[(-50i16) as u16, (-200i16) as u16, 950, 800]));
+        let mut hhea = vec![0; 36];
+        hhea[4..8].copy_from_slice(&be(&[800, (-200i16) as u16]));
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+        let hmtx = be(&[500, 0, 600, 0]);
+        // format 4 with the segments 'A'..='B' and the final 0xffff
+        let subtable = be(&[4, 32, 0, 4, 4, 1, 0, 0x42, 0xffff, 0, 0x41, 0xffff, 1u16.wrapping_sub(0x41), 1, 0, 0]);
+        let cmap = [be(&[0, 1, 3, 1, 0, 12]), subtable].concat();
+        let mut os2 = vec![0; 78];
+        os2[4..10].copy_from_slice(&be(&[400, 5, fs_type]));
+        let utf16 = |s: &str| be(&s.encode_utf16().collect::<Vec<u16>>());
+        let (family, postscript) = (utf16(family), utf16("Fixture-Regular"));
+        let name = [
+            be(&[0, 2, 30]),
+            be(&[3, 1, 0x409, 1, family.len() as u16, 0]),
+            be(&[3, 1, 0x409, 6, postscript.len() as u16, family.len() as u16]),
+            family,
+            postscript,
+        ].concat();
+
+        let tables: [(&[u8], Vec<u8>); 6] = [(b"head", head), (b"hhea", hhea), (b"hmtx", hmtx), (b"OS/2", os2), (b"cmap", cmap), (b"name", name)];
+        let mut data = be(&[1, 0, tables.len() as u16, 0, 0, 0]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in &tables {
+            data.extend_from_slice(tag);
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len().div_ceil(4) * 4;
+        }
+        for (_, table) in &tables {
+            data.extend_from_slice(table);
+            data.resize(data.len().div_ceil(4) * 4, 0);
+        }
+        data
+    }
+
+    fn info(data: &[u8], name: &str) -> Option<FontInfo> {
+        let path = std::env::temp_dir().join(format!("fonts-{}-{}.ttf", name, std::process::id()));
+        fs::write(&path, data).unwrap();
+        let info = FontFile::open(&path).and_then(|mut font| font_info(&mut font));
+        fs::remove_file(&path).unwrap();
+        info
+    }
+
+    #[test]
+    fn tables() {
+        let data = fixture("Fixture", 0);
+        assert_eq!(u16_at(&data, 4), Some(6));
+        assert_eq!(u16_at(&data, data.len() - 1), None);
+        assert_eq!(table(&data, b"head"), Some((108, 54)));
+        assert_eq!(table(&data, b"glyf"), None);
+    }
+
+    #[test]
+    fn names_and_embedding() {
+        let info = info(&fixture("Fixture Sans", 0), "names").unwrap();
+        assert_eq!((info.family.as_str(), info.postscript.as_str()), ("Fixture Sans", "Fixture-Regular"));
+        assert_eq!((info.weight, info.width, info.italic, info.embeddable), (400, 5, false, true));
+        // restricted, unless preview and print embedding is allowed; bitmap only
+        assert!(!self::info(&fixture("Fixture", 0x0002), "restricted").unwrap().embeddable);
+        assert!(self::info(&fixture("Fixture", 0x0006), "preview").unwrap().embeddable);
+        assert!(!self::info(&fixture("Fixture", 0x0200), "bitmap").unwrap().embeddable);
+    }
+
+    #[test]
+    fn glyphs() {
+        let font = TrueType::parse("Fixture-Regular".to_string(), fixture("Fixture", 0)).unwrap();
+        assert_eq!((font.units_per_em, font.ascent, font.descent), (1000.0, 800.0, -200.0));
+        assert_eq!(font.bbox, [-50.0, -200.0, 950.0, 800.0]);
+        assert_eq!((font.glyph('A'), font.glyph('B'), font.glyph('C'), font.glyph('é')), (Some(1), Some(2), None, None));
+        // glyphs past the metrics take the last advance
+        assert_eq!((font.advance(0), font.advance(1), font.advance(2)), (500.0, 600.0, 600.0));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = fixture("Fixture", 0);
+        for length in 0..data.len() - 3 {
+            let data = &data[..length];
+            if let Some(font) = TrueType::parse(String::new(), data.to_vec()) {
+                font.glyph('A');
+                font.advance(1);
+            }
+        }
+        // the name table ends the file
+        assert!(TrueType::parse(String::new(), data[..200].to_vec()).is_none());
+        assert!(info(&data[..data.len() - 4], "truncated").is_none());
+        assert!(info(&data[..100], "directory").is_none());
+        assert!(info(b"not a font", "text").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle() {
+        let dir = std::env::temp_dir().join(format!("fonts-cycle-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.ttf"), fixture("Fixture", 0)).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+        let mut files = Vec::new();
+        collect_font_files(&dir, &mut files, &mut HashSet::new());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("sub/a.ttf")]);
+    }
+}
@@ -0,0 +1,232 @@
+// Fortran unformatted sequential files: every record is [length][data][length], with
+// 4 byte (gfortran, ifort) or 8 byte (old g77 builds) length markers in either byte order
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordMarker {
+    // the first combination of size and byte order that fits the whole file
+    Auto,
+    Four,
+    Eight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endian {
+    Auto,
+    Little,
+    Big,
+}
+
+// Type of the values in the records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordType {
+    F64,
+    F32,
+    I32,
+    I64,
+}
+
+pub struct FortranOptions {
+    pub marker: RecordMarker,
+    pub endian: Endian,
+    pub record_type: RecordType,
+    // records skipped at the start, e.g. a header with the grid size
+    pub skip_records: usize,
+    // false: every record is a column, as written by write(u) x; write(u) y
+    // true: every record is a row, as written by write(u) t, e in a loop
+    pub rows: bool,
+}
+
+impl FortranOptions {
+    pub fn new() -> FortranOptions {
+        FortranOptions {
+            marker: RecordMarker::Auto,
+            endian: Endian::Auto,
+            record_type: RecordType::F64,
+            skip_records: 0,
+            rows: false,
+        }
+    }
+}
+
+impl Default for FortranOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Columns from the records; with rows, short rows are padded with NaN.
+// A file that doesn't fit the options is an InvalidData error.
+pub fn read_fortran_records<P: AsRef<Path>>(path: P, options: &FortranOptions) -> io::Result<Vec<Vec<f64>>> {
+    let path = path.as_ref();
+    let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let sizes = match options.marker {
+        RecordMarker::Four => vec![4],
+        RecordMarker::Eight => vec![8],
+        RecordMarker::Auto => vec![4, 8],
+    };
+    let orders = match options.endian {
+        Endian::Little => vec![false],
+        Endian::Big => vec![true],
+        Endian::Auto => vec![false, true],
+    };
+    let candidates: Vec<(usize, bool)> = sizes.iter()
+        .flat_map(|&marker| orders.iter().map(move |&big| (marker, big)))
+        .collect();
+    // a single candidate is checked record by record below, with the position of any mismatch
+    let (marker, big) = if candidates.len() == 1 {
+        candidates[0]
+    } else {
+        candidates.into_iter()
+            .find(|&(marker, big)| fits(&mut file, size, marker, big))
+            .ok_or_else(|| invalid(format!("{:?} is not a Fortran unformatted sequential file with 4 or 8 byte record markers", path)))?
+    };
+
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+    let width = match options.record_type {
+        RecordType::F64 | RecordType::I64 => 8,
+        RecordType::F32 | RecordType::I32 => 4,
+    };
+    let mut records = Vec::new();
+    let mut offset = 0;
+    let mut k = 0;
+    while offset < size {
+        let failed = |what: &str| invalid(format!("Can't read {} of record {} at byte {} of {:?}", what, k + 1, offset, path));
+        let length = read_marker(&mut reader, marker, big).ok_or_else(|| failed("the leading length"))?;
+        // a wrong marker size or byte order reads a length that can be anything
+        if length > (size - offset).saturating_sub(2 * marker as u64) {
+            return Err(invalid(format!("Record {} at byte {} of {:?} has length {}, which exceeds the file", k + 1, offset, path, length)));
+        }
+        let mut data = vec![0; length as usize];
+        reader.read_exact(&mut data).map_err(|_| failed("the data"))?;
+        let trailing = read_marker(&mut reader, marker, big).ok_or_else(|| failed("the trailing length"))?;
+        if trailing != length {
+            return Err(invalid(format!("Record {} at byte {} of {:?} has leading length {} and trailing length {}", k + 1, offset, path, length, trailing)));
+        }
+        if k >= options.skip_records {
+            if !data.len().is_multiple_of(width) {
+                return Err(invalid(format!("Record {} of {:?} has {} bytes, not a multiple of {}", k + 1, path, data.len(), width)));
+            }
+            records.push(decode(&data, options.record_type, big));
+        }
+        offset += 2 * marker as u64 + length;
+        k += 1;
+    }
+
+    if !options.rows {
+        return Ok(records);
+    }
+    let columns = records.iter().map(|r| r.len()).max().unwrap_or(0);
+    Ok((0..columns)
+        .map(|j| records.iter().map(|r| r.get(j).cloned().unwrap_or(f64::NAN)).collect())
+        .collect())
+}
+
+// Every record's leading and trailing markers agree and the last record ends the file
+fn fits(file: &mut File, size: u64, marker: usize, big: bool) -> bool {
+    let mut offset = 0;
+    while offset < size {
+        let leading = file.seek(SeekFrom::Start(offset)).ok().and_then(|_| read_marker(file, marker, big));
+        let length = match leading {
+            Some(length) if offset + 2 * marker as u64 + length <= size => length,
+            _ => return false,
+        };
+        let trailing = file.seek(SeekFrom::Start(offset + marker as u64 + length)).ok().and_then(|_| read_marker(file, marker, big));
+        if trailing != Some(length) {
+            return false;
+        }
+        offset += 2 * marker as u64 + length;
+    }
+    size > 0
+}
+
+// Record length in bytes; negative lengths, used by gfortran for records split into
+// subrecords over 2 GB, are not supported
+fn read_marker<R: Read>(reader: &mut R, marker: usize, big: bool) -> Option<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes[..marker]).ok()?;
+    let length = match (marker, big) {
+        (4, false) => i32::from_le_bytes(bytes[..4].try_into().unwrap()) as i64,
+        (4, true) => i32::from_be_bytes(bytes[..4].try_into().unwrap()) as i64,
+        (_, false) => i64::from_le_bytes(bytes),
+        (_, true) => i64::from_be_bytes(bytes),
+    };
+    u64::try_from(length).ok()
+}
+
+fn decode(data: &[u8], value: RecordType, big: bool) -> Vec<f64> {
+    macro_rules! convert {
+        ($t:ty) => {
+            data.chunks_exact(std::mem::size_of::<$t>()).map(|b| {
+                let b = b.try_into().unwrap();
+                (if big { <$t>::from_be_bytes(b) } else { <$t>::from_le_bytes(b) }) as f64
+            }).collect()
+        };
+    }
+    match value {
+        RecordType::F64 => convert!(f64),
+        RecordType::F32 => convert!(f32),
+        RecordType::I32 => convert!(i32),
+        RecordType::I64 => convert!(i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("fortran-{}-{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn record(values: &[f64]) -> Vec<u8> {
+        let length = (8 * values.len() as u32).to_le_bytes();
+        let mut bytes = length.to_vec();
+        values.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        bytes.extend(length);
+        bytes
+    }
+
+    #[test]
+    fn reads_records_as_rows() {
+        let mut bytes = record(&[1.0, 2.0]);
+        bytes.extend(record(&[3.0]));
+        let path = write_file("rows", &bytes);
+        let mut options = FortranOptions::new();
+        options.rows = true;
+        let columns = read_fortran_records(&path, &options).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(columns[0], vec![1.0, 3.0]);
+        assert_eq!(columns[1][0], 2.0);
+        assert!(columns[1][1].is_nan());
+    }
+
+    #[test]
+    fn corrupt_marker_is_an_error() {
+        let mut bytes = record(&[1.0, 2.0]);
+        bytes[..4].copy_from_slice(&0x7fff_fff0u32.to_le_bytes());
+        let path = write_file("corrupt", &bytes);
+        let mut options = FortranOptions::new();
+        options.marker = RecordMarker::Four;
+        options.endian = Endian::Little;
+        let error = read_fortran_records(&path, &options).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("exceeds the file"));
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let error = read_fortran_records("/nonexistent/data.bin", &FortranOptions::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,121 @@
+// Polars DataFrames as plot input and in the column files of the file module, behind the polars feature
+use std::borrow::Cow;
+use std::path::Path;
+
+use polars::prelude::{Column, DataFrame, DataType};
+
+use crate::file::{read_columns_from_file, save_named_columns_to_file};
+use crate::plot::{line_plot, PlotPar};
+use crate::series::{IntoSeries, Series};
+
+// Float64 columns without nulls in a single chunk are borrowed,
+// other numeric columns are converted with nulls as NaN.
+// Missing and non-numeric columns are errors.
+pub fn column<'a>(df: &'a DataFrame, name: &str) -> Result<Cow<'a, [f64]>, String> {
+    let column = df.column(name).map_err(|_| format!("No column {:?} in the DataFrame", name))?;
+    column_values(column)
+}
+
+fn column_values(column: &Column) -> Result<Cow<'_, [f64]>, String> {
+    let series = column.as_materialized_series();
+    if let Ok(values) = series.f64().and_then(|values| values.cont_slice()) {
+        return Ok(Cow::Borrowed(values));
+    }
+    // strings that look like numbers would be parsed by cast
+    let values = series.cast(&DataType::Float64).ok()
+        .filter(|_| series.dtype().is_primitive_numeric() || series.dtype().is_bool())
+        .ok_or_else(|| format!("Column {:?} of type {} is not numeric", series.name(), series.dtype()))?;
+    Ok(Cow::Owned(values.f64().unwrap().iter().map(|v| v.unwrap_or(f64::NAN)).collect()))
+}
+
+// Every y column against the x column
+pub fn frame_series<'a>(df: &'a DataFrame, x: &str, y: &[&str]) -> Result<Series<'a>, String> {
+    let ys = y.iter().map(|name| column(df, name)).collect::<Result<Vec<_>, _>>()?;
+    Ok(Series::shared_x(column(df, x)?, ys))
+}
+
+// line_plot of the named columns, legends missing from plot_par are the column names
+pub fn frame_plot(df: &DataFrame, x: &str, y: &[&str], plot_par: &PlotPar) -> Result<(), String> {
+    let mut plot_par = plot_par.clone();
+    for name in y.iter().skip(plot_par.legends.len()) {
+        plot_par.legends.push(name.to_string());
+    }
+    line_plot(frame_series(df, x, y)?, &plot_par);
+    Ok(())
+}
+
+// The first column is x and the others are y; panics on non-numeric columns, see frame_series
+impl<'a> IntoSeries<'a> for &'a DataFrame {
+    fn into_series(self) -> Series<'a> {
+        let mut columns = self.get_columns().iter().map(|column| column_values(column).unwrap_or_else(|e| panic!("{}", e)));
+        let x = columns.next().expect("No data columns");
+        Series::shared_x(x, columns)
+    }
+}
+
+// All columns with their names as the header line
+pub fn save_frame_to_file(df: &DataFrame, dir_name: &str, file_name: &str) {
+    let names: Vec<&str> = df.get_column_names().into_iter().map(|name| name.as_str()).collect();
+    save_named_columns_to_file(df, &names, dir_name, file_name);
+}
+
+// Columns without a name in the header are called column_1, column_2, ...
+pub fn read_frame_from_file<P: AsRef<Path>>(path: P) -> DataFrame {
+    let (names, columns) = read_columns_from_file(path);
+    let columns = columns.into_iter().enumerate()
+        .map(|(k, values)| {
+            let name = names.get(k).cloned().unwrap_or_else(|| format!("column_{}", k + 1));
+            Column::new(name.into(), values)
+        })
+        .collect();
+    DataFrame::new(columns).expect("Columns with equal names in the file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::df;
+
+    fn frame() -> DataFrame {
+        df!(
+            "t" => [0.0, 1.0, 2.0],
+            "v" => [1i32, 2, 3],
+            "w" => [Some(0.5), None, Some(1.5)],
+            "name" => ["a", "b", "c"],
+        ).unwrap()
+    }
+
+    #[test]
+    fn columns_by_name() {
+        let df = frame();
+        assert!(matches!(column(&df, "t").unwrap(), Cow::Borrowed(&[0.0, 1.0, 2.0])));
+        assert_eq!(column(&df, "v").unwrap(), Cow::<[f64]>::Owned(vec![1.0, 2.0, 3.0]));
+        let w = column(&df, "w").unwrap();
+        assert!(w[1].is_nan() && w[2] == 1.5);
+
+        let series = frame_series(&df, "t", &["v", "t"]).unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!((series.x(1), series.y(0)), (&[0.0, 1.0, 2.0][..], &[1.0, 2.0, 3.0][..]));
+    }
+
+    #[test]
+    fn missing_and_non_numeric() {
+        let df = frame();
+        assert_eq!(column(&df, "u").unwrap_err(), "No column \"u\" in the DataFrame");
+        assert_eq!(column(&df, "name").unwrap_err(), "Column \"name\" of type str is not numeric");
+        assert!(frame_series(&df, "t", &["v", "u"]).is_err());
+        assert!(frame_series(&df, "name", &["v"]).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("frames-{}", std::process::id()));
+        let df = frame().drop("name").unwrap();
+        save_frame_to_file(&df, dir.to_str().unwrap(), "a.dat");
+        let read = read_frame_from_file(dir.join("a.dat"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read.get_column_names(), df.get_column_names());
+        assert_eq!(column(&read, "v").unwrap(), column(&df, "v").unwrap());
+        assert!(column(&read, "w").unwrap()[1].is_nan());
+    }
+}
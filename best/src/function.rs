@@ -0,0 +1,174 @@
+// Plotting closures directly with adaptive sampling
+use crate::plot::{make_layout, make_trace, write_plot, PlotPar, Style};
+use crate::render::Figure;
+
+pub struct Sampling {
+    pub initial_points: usize,
+    pub max_depth: usize,
+    // allowed deviation from a straight line, relative to the y range
+    pub tolerance: f64,
+    // no refinement where the function is outside this range
+    pub y_range: Option<[f64; 2]>,
+}
+
+impl Sampling {
+    pub fn new() -> Sampling {
+        Sampling {
+            initial_points: 64,
+            max_depth: 10,
+            tolerance: 1e-3,
+            y_range: None,
+        }
+    }
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Samples f on range, bisecting intervals where the curve deviates from a straight
+// line or crosses into NaN/infinity. Non-finite values are returned as NaN, which
+// breaks the line when plotted, and so is a pole or jump that is still there at max_depth.
+pub fn sample_function<F: Fn(f64) -> f64>(f: F, range: [f64; 2], sampling: &Sampling) -> (Vec<f64>, Vec<f64>) {
+    let n = sampling.initial_points.max(2);
+    let dx = (range[1] - range[0]) / ((n - 1) as f64);
+    let x0: Vec<f64> = (0..n).map(|i| range[0] + i as f64 * dx).collect();
+    let y0: Vec<f64> = x0.iter().map(|&x| f(x)).collect();
+
+    let scale = match sampling.y_range {
+        Some(r) => (r[1] - r[0]).abs(),
+        None => {
+            let finite = y0.iter().filter(|y| y.is_finite());
+            let min = finite.clone().fold(f64::INFINITY, |a, &b| a.min(b));
+            let max = finite.fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+            max - min
+        }
+    };
+    let tol = if scale.is_finite() && scale > 0.0 { sampling.tolerance * scale } else { sampling.tolerance };
+
+    let mut out = (vec![x0[0]], vec![y0[0]]);
+    for i in 1..n {
+        refine(&f, (x0[i-1], y0[i-1]), (x0[i], y0[i]), 0, tol, sampling, &mut out);
+    }
+    let (x, mut y) = out;
+    for y in y.iter_mut() {
+        if !y.is_finite() {
+            *y = f64::NAN;
+        }
+    }
+    (x, y)
+}
+
+fn refine<F: Fn(f64) -> f64>(f: &F, a: (f64, f64), b: (f64, f64), depth: usize, tol: f64, sampling: &Sampling, out: &mut (Vec<f64>, Vec<f64>)) {
+    let xm = 0.5 * (a.0 + b.0);
+    let m = (xm, f(xm));
+    let finite = [a.1, m.1, b.1].iter().filter(|v| v.is_finite()).count();
+    let refine_here = match finite {
+        3 => (m.1 - 0.5 * (a.1 + b.1)).abs() > tol && !outside(&[a.1, m.1, b.1], sampling.y_range),
+        0 => false,
+        _ => true,
+    };
+    if refine_here && depth < sampling.max_depth {
+        refine(f, a, m, depth + 1, tol, sampling, out);
+        refine(f, m, b, depth + 1, tol, sampling, out);
+        return;
+    }
+    if refine_here && finite == 3 && is_jump(a.1, m.1, b.1, sampling.y_range) {
+        out.0.push(m.0);
+        out.1.push(f64::NAN);
+    }
+    out.0.push(b.0);
+    out.1.push(b.1);
+}
+
+// An interval that bisection can't make straight: a pole, where the middle is beyond
+// both ends, or a jump across more than the visible range
+fn is_jump(a: f64, m: f64, b: f64, y_range: Option<[f64; 2]>) -> bool {
+    let pole = m < a.min(b) || m > a.max(b);
+    pole || y_range.is_some_and(|r| (b - a).abs() > (r[1] - r[0]).abs())
+}
+
+// All values on the same side of the visible range
+fn outside(values: &[f64], y_range: Option<[f64; 2]>) -> bool {
+    match y_range {
+        Some(r) => {
+            let lo = r[0].min(r[1]);
+            let hi = r[0].max(r[1]);
+            values.iter().all(|&v| v < lo) || values.iter().all(|&v| v > hi)
+        },
+        None => false,
+    }
+}
+
+// Plots every function over range and returns the sampled series, e.g. for save_columns_to_file
+pub fn function_plot(functions: &[&dyn Fn(f64) -> f64], range: [f64; 2], plot_par: &PlotPar) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let mut sampling = Sampling::new();
+    // about one pixel of the plot height
+    sampling.tolerance = 1.0 / plot_par.height as f64;
+    if plot_par.custom_range_y {
+        sampling.y_range = Some(plot_par.range_y);
+    }
+
+    let (xs, ys): (Vec<Vec<f64>>, Vec<Vec<f64>>) = functions.iter()
+        .map(|f| sample_function(f, range, &sampling))
+        .unzip();
+
+    let style = Style::new(plot_par);
+    let mut figure = Figure::new(make_layout(plot_par, &style));
+    for l in 0..xs.len() {
+        figure.add_trace(make_trace(&xs[l], &ys[l], l, plot_par, &style));
+    }
+
+    write_plot(&figure, plot_par);
+    (xs, ys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the finite points between gaps, which plotly joins with lines
+    fn segments(x: &[f64], y: &[f64]) -> Vec<Vec<(f64, f64)>> {
+        let mut segments = vec![Vec::new()];
+        for (&x, &y) in x.iter().zip(y) {
+            if y.is_nan() {
+                segments.push(Vec::new());
+            } else {
+                segments.last_mut().unwrap().push((x, y));
+            }
+        }
+        segments.retain(|s| !s.is_empty());
+        segments
+    }
+
+    #[test]
+    fn pole() {
+        let (x, y) = sample_function(|x| 1.0 / x, [-1.0, 1.0], &Sampling::new());
+        let segments = segments(&x, &y);
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].iter().all(|p| p.0 < 0.0) && segments[1].iter().all(|p| p.0 > 0.0));
+    }
+
+    #[test]
+    fn nan_region() {
+        let (x, y) = sample_function(f64::sqrt, [-1.0, 1.0], &Sampling::new());
+        let segments = segments(&x, &y);
+        assert_eq!(segments.len(), 1);
+        assert!(segments[0].iter().all(|p| p.0 >= 0.0));
+        assert!(segments[0][0].0 < 1e-3);
+        assert!(x.iter().zip(&y).all(|(x, y)| (*x < 0.0) == y.is_nan()));
+    }
+
+    #[test]
+    fn smooth() {
+        let range = [0.0, 2.0 * std::f64::consts::PI];
+        let (x, y) = sample_function(f64::sin, range, &Sampling::new());
+        assert_eq!((x[0], *x.last().unwrap()), (range[0], range[1]));
+        assert!(x.windows(2).all(|w| w[0] < w[1]));
+        assert!(x.iter().zip(&y).all(|(x, y)| y.is_finite() && (x.sin() - y).abs() < 1e-12));
+        // straight between samples to the tolerance, 2e-3 of the y range of 2
+        assert!(x.windows(2).zip(y.windows(2)).all(|(x, y)| ((0.5 * (x[0] + x[1])).sin() - 0.5 * (y[0] + y[1])).abs() <= 2e-3));
+    }
+}
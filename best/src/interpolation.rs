@@ -0,0 +1,249 @@
+// Interpolation and resampling of series onto a common x grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Linear,
+    // natural cubic spline
+    CubicSpline,
+    // monotone piecewise cubic Hermite, no overshoot between points
+    Pchip,
+    Nearest,
+}
+
+// What to return outside the x range of the data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extrapolation {
+    Nan,
+    // hold the first and last values
+    Constant,
+    // continue the end piece of the interpolant
+    Extend,
+    // an error for the first x outside
+    Error,
+}
+
+pub struct Interpolator {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    // first derivatives at the points for the cubic methods
+    d: Vec<f64>,
+    method: Method,
+    extrapolation: Extrapolation,
+}
+
+impl Interpolator {
+    // Points with NaN are dropped and the rest sorted by x
+    pub fn new(x: &[f64], y: &[f64], method: Method, extrapolation: Extrapolation) -> Interpolator {
+        let mut points: Vec<(f64, f64)> = x.iter().zip(y.iter())
+            .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+            .map(|(x, y)| (*x, *y))
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.dedup_by(|a, b| a.0 == b.0);
+        let (x, y): (Vec<f64>, Vec<f64>) = points.into_iter().unzip();
+        let d = match method {
+            Method::CubicSpline => spline_derivatives(&x, &y),
+            Method::Pchip => pchip_derivatives(&x, &y),
+            Method::Linear | Method::Nearest => Vec::new(),
+        };
+        Interpolator { x, y, d, method, extrapolation }
+    }
+
+    // Err only with Extrapolation::Error for x outside the data
+    pub fn eval(&self, x: f64) -> Result<f64, String> {
+        let n = self.x.len();
+        if n == 0 || x.is_nan() {
+            return Ok(f64::NAN);
+        }
+        if n == 1 {
+            return Ok(self.y[0]);
+        }
+        if x < self.x[0] || x > self.x[n - 1] {
+            match self.extrapolation {
+                Extrapolation::Nan => return Ok(f64::NAN),
+                Extrapolation::Constant => return Ok(if x < self.x[0] { self.y[0] } else { self.y[n - 1] }),
+                Extrapolation::Error => return Err(format!("x = {} outside the data range [{}, {}]", x, self.x[0], self.x[n - 1])),
+                Extrapolation::Extend => {},
+            }
+        }
+        // interval [x[i], x[i+1]] containing x, or the end interval outside the data
+        let i = self.x.partition_point(|&xi| xi <= x).clamp(1, n - 1) - 1;
+        let h = self.x[i + 1] - self.x[i];
+        let t = (x - self.x[i]) / h;
+        Ok(match self.method {
+            Method::Nearest => if t < 0.5 { self.y[i] } else { self.y[i + 1] },
+            Method::Linear => self.y[i] + t * (self.y[i + 1] - self.y[i]),
+            Method::CubicSpline | Method::Pchip => {
+                let h00 = (1.0 + 2.0 * t) * (1.0 - t).powi(2);
+                let h10 = t * (1.0 - t).powi(2);
+                let h01 = t * t * (3.0 - 2.0 * t);
+                let h11 = t * t * (t - 1.0);
+                h00 * self.y[i] + h10 * h * self.d[i] + h01 * self.y[i + 1] + h11 * h * self.d[i + 1]
+            },
+        })
+    }
+
+    pub fn eval_all(&self, x: &[f64]) -> Result<Vec<f64>, String> {
+        x.iter().map(|&x| self.eval(x)).collect()
+    }
+}
+
+pub fn interpolate(x: &[f64], y: &[f64], x_new: &[f64], method: Method, extrapolation: Extrapolation) -> Result<Vec<f64>, String> {
+    Interpolator::new(x, y, method, extrapolation).eval_all(x_new)
+}
+
+// Every series resampled onto grid, giving columns of equal length for save_columns_to_file
+pub fn resample(x: &[Vec<f64>], y: &[Vec<f64>], grid: &[f64], method: Method, extrapolation: Extrapolation) -> Result<Vec<Vec<f64>>, String> {
+    x.iter().zip(y.iter())
+        .map(|(x, y)| interpolate(x, y, grid, method, extrapolation))
+        .collect()
+}
+
+// n evenly spaced points over the x range covered by all series, None when there is none
+pub fn overlap_grid(x: &[Vec<f64>], n: usize) -> Option<Vec<f64>> {
+    let finite = |x: &Vec<f64>| x.iter().cloned().filter(|v| v.is_finite()).collect::<Vec<f64>>();
+    let lo = x.iter().map(|x| finite(x).into_iter().fold(f64::INFINITY, f64::min)).fold(f64::NEG_INFINITY, f64::max);
+    let hi = x.iter().map(|x| finite(x).into_iter().fold(f64::NEG_INFINITY, f64::max)).fold(f64::INFINITY, f64::min);
+    if lo > hi || !lo.is_finite() || !hi.is_finite() {
+        return None;
+    }
+    if n < 2 {
+        return Some(vec![lo]);
+    }
+    let dx = (hi - lo) / ((n - 1) as f64);
+    Some((0..n).map(|i| lo + i as f64 * dx).collect())
+}
+
+// All distinct x values of all series, sorted
+pub fn merged_grid(x: &[Vec<f64>]) -> Vec<f64> {
+    let mut grid: Vec<f64> = x.iter().flatten().cloned().filter(|v| !v.is_nan()).collect();
+    grid.sort_by(|a, b| a.total_cmp(b));
+    grid.dedup();
+    grid
+}
+
+// Natural cubic spline: second derivatives from the tridiagonal system, then first derivatives
+fn spline_derivatives(x: &[f64], y: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    if n < 3 {
+        return linear_derivatives(x, y);
+    }
+    let h: Vec<f64> = (0..n - 1).map(|i| x[i + 1] - x[i]).collect();
+    let mut diag = vec![1.0; n];
+    let mut upper = vec![0.0; n];
+    let mut lower = vec![0.0; n];
+    let mut rhs = vec![0.0; n];
+    for i in 1..n - 1 {
+        lower[i] = h[i - 1];
+        diag[i] = 2.0 * (h[i - 1] + h[i]);
+        upper[i] = h[i];
+        rhs[i] = 6.0 * ((y[i + 1] - y[i]) / h[i] - (y[i] - y[i - 1]) / h[i - 1]);
+    }
+    // Thomas algorithm
+    for i in 1..n {
+        let m = lower[i] / diag[i - 1];
+        diag[i] -= m * upper[i - 1];
+        rhs[i] -= m * rhs[i - 1];
+    }
+    let mut m2 = vec![0.0; n];
+    m2[n - 1] = rhs[n - 1] / diag[n - 1];
+    for i in (0..n - 1).rev() {
+        m2[i] = (rhs[i] - upper[i] * m2[i + 1]) / diag[i];
+    }
+    let mut d: Vec<f64> = (0..n - 1)
+        .map(|i| (y[i + 1] - y[i]) / h[i] - h[i] * (2.0 * m2[i] + m2[i + 1]) / 6.0)
+        .collect();
+    d.push((y[n - 1] - y[n - 2]) / h[n - 2] + h[n - 2] * (m2[n - 2] + 2.0 * m2[n - 1]) / 6.0);
+    d
+}
+
+// Fritsch-Carlson derivatives with the three point end formula
+fn pchip_derivatives(x: &[f64], y: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    if n < 3 {
+        return linear_derivatives(x, y);
+    }
+    let h: Vec<f64> = (0..n - 1).map(|i| x[i + 1] - x[i]).collect();
+    let delta: Vec<f64> = (0..n - 1).map(|i| (y[i + 1] - y[i]) / h[i]).collect();
+    let mut d = vec![0.0; n];
+    for i in 1..n - 1 {
+        if delta[i - 1] * delta[i] > 0.0 {
+            let w1 = 2.0 * h[i] + h[i - 1];
+            let w2 = h[i] + 2.0 * h[i - 1];
+            d[i] = (w1 + w2) / (w1 / delta[i - 1] + w2 / delta[i]);
+        }
+    }
+    let end = |h0: f64, h1: f64, d0: f64, d1: f64| {
+        let d = ((2.0 * h0 + h1) * d0 - h0 * d1) / (h0 + h1);
+        if d * d0 <= 0.0 {
+            0.0
+        } else if d0 * d1 <= 0.0 && d.abs() > 3.0 * d0.abs() {
+            3.0 * d0
+        } else {
+            d
+        }
+    };
+    d[0] = end(h[0], h[1], delta[0], delta[1]);
+    d[n - 1] = end(h[n - 2], h[n - 3], delta[n - 2], delta[n - 3]);
+    d
+}
+
+fn linear_derivatives(x: &[f64], y: &[f64]) -> Vec<f64> {
+    if x.len() < 2 {
+        return vec![0.0; x.len()];
+    }
+    let slope = (y[1] - y[0]) / (x[1] - x[0]);
+    vec![slope; 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn methods() {
+        let x = [2.0, 0.0, 1.0, f64::NAN];
+        let y = [4.0, 0.0, 1.0, 5.0];
+        assert_eq!(interpolate(&x, &y, &[0.5, 1.5], Method::Linear, Extrapolation::Nan).unwrap(), vec![0.5, 2.5]);
+        assert_eq!(interpolate(&x, &y, &[0.4, 0.6], Method::Nearest, Extrapolation::Nan).unwrap(), vec![0.0, 1.0]);
+        // a natural spline through three points of a line is the line
+        let line = interpolate(&[0.0, 1.0, 3.0], &[1.0, 3.0, 7.0], &[0.5, 2.0], Method::CubicSpline, Extrapolation::Nan).unwrap();
+        assert!((line[0] - 2.0).abs() < 1e-12 && (line[1] - 5.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pchip_keeps_monotone_data_monotone() {
+        let x = [0.0, 1.0, 2.0, 3.0];
+        let y = [0.0, 0.1, 0.2, 5.0];
+        let grid: Vec<f64> = (0..=30).map(|i| i as f64 * 0.1).collect();
+        let values = interpolate(&x, &y, &grid, Method::Pchip, Extrapolation::Nan).unwrap();
+        assert!(values.windows(2).all(|w| w[1] >= w[0]));
+        assert!(values.iter().all(|v| (0.0..=5.0).contains(v)));
+    }
+
+    #[test]
+    fn extrapolation() {
+        let (x, y) = ([0.0, 1.0], [0.0, 2.0]);
+        assert!(interpolate(&x, &y, &[2.0], Method::Linear, Extrapolation::Nan).unwrap()[0].is_nan());
+        assert_eq!(interpolate(&x, &y, &[-1.0, 2.0], Method::Linear, Extrapolation::Constant).unwrap(), vec![0.0, 2.0]);
+        assert_eq!(interpolate(&x, &y, &[-1.0, 2.0], Method::Linear, Extrapolation::Extend).unwrap(), vec![-2.0, 4.0]);
+    }
+
+    #[test]
+    fn extrapolation_errors() {
+        let (x, y) = ([0.0, 1.0], [0.0, 2.0]);
+        assert_eq!(interpolate(&x, &y, &[0.5, 1.0], Method::Linear, Extrapolation::Error), Ok(vec![1.0, 2.0]));
+        let error = interpolate(&x, &y, &[0.5, 1.5], Method::Linear, Extrapolation::Error).unwrap_err();
+        assert!(error.contains("outside the data range"), "{}", error);
+    }
+
+    #[test]
+    fn grids() {
+        let x = vec![vec![0.0, 1.0, 4.0], vec![0.5, 2.0, f64::NAN]];
+        assert_eq!(overlap_grid(&x, 4), Some(vec![0.5, 1.0, 1.5, 2.0]));
+        assert_eq!(overlap_grid(&[vec![0.0, 1.0], vec![2.0, 3.0]], 4), None);
+        assert_eq!(overlap_grid(&[vec![f64::NAN]], 4), None);
+        assert_eq!(merged_grid(&x), vec![0.0, 0.5, 1.0, 2.0, 4.0]);
+        let y = vec![vec![0.0, 1.0, 4.0], vec![1.0, 4.0, 0.0]];
+        assert_eq!(resample(&x, &y, &[1.0, 2.0], Method::Linear, Extrapolation::Nan).unwrap(), vec![vec![1.0, 2.0], vec![2.0, 4.0]]);
+    }
+}
@@ -0,0 +1,28 @@
+#[cfg(feature = "ndarray")]
+mod arrays;
+#[cfg(feature = "arrow")]
+mod columnar;
+mod compression;
+pub mod convergence;
+pub mod curves;
+pub mod downsample;
+pub mod file;
+pub mod fitting;
+#[cfg(feature = "polars")]
+pub mod frames;
+mod fonts;
+mod fortran;
+pub mod function;
+pub mod interpolation;
+pub mod manifest;
+mod npy;
+pub mod pdf;
+pub mod plot;
+pub mod queue;
+mod render;
+pub mod report;
+pub mod series;
+pub mod spec;
+mod tokenizer;
+pub mod transforms;
+pub mod watch;
@@ -1,20 +1,18 @@
-mod file;
-mod plot;
+use taylor_plotly_example::{file, function, plot};
 
 fn main() {
-    let n: usize = 250;
     let x_min = 0.0;
     let x_max = 3.0;
-    let dx = (x_max-x_min)/(n as f64);
-    let x: Vec<f64> = (0..n).map(|n| n as f64 * dx + x_min).collect();
-    let y1: Vec<f64> = x.clone().into_iter().map(|x| (x*x).cos()).collect();
-    let y2: Vec<f64> = x.clone().into_iter().map(|x| 1.0-x.powi(4)/2.0).collect();
-    let y3: Vec<f64> = x.clone().into_iter().map(|x| 1.0-x.powi(4)/2.0+x.powi(8)/24.0).collect();
-    let y4: Vec<f64> = x.clone().into_iter().map(|x| 1.0-x.powi(4)/2.0+x.powi(8)/24.0-x.powi(12)/720.0).collect();
-    let y5: Vec<f64> = x.clone().into_iter().map(|x| 1.0-x.powi(4)/2.0+x.powi(8)/24.0-x.powi(12)/720.0+x.powi(16)/720.0/7.0/8.0).collect();
+    let functions: [&dyn Fn(f64) -> f64; 5] = [
+        &|x| (x*x).cos(),
+        &|x| 1.0-x.powi(4)/2.0,
+        &|x| 1.0-x.powi(4)/2.0+x.powi(8)/24.0,
+        &|x| 1.0-x.powi(4)/2.0+x.powi(8)/24.0-x.powi(12)/720.0,
+        &|x| 1.0-x.powi(4)/2.0+x.powi(8)/24.0-x.powi(12)/720.0+x.powi(16)/720.0/7.0/8.0,
+    ];
 
-    let flnm = format!("Taylor_cos2");
-    let title = format!(r"Приближения рядом Тейлора для cos(x<sup>2</sup>)");
+    let flnm = "Taylor_cos2".to_string();
+    let title = r"Приближения рядом Тейлора для cos(x<sup>2</sup>)".to_string();
     //set parameters
     let mut plot_par = plot::PlotPar::new(
         1600, 1080,
@@ -33,14 +31,15 @@ fn main() {
     // change legend alignments or plotting mode
     plot_par.legend_al = plot::LegendAl::BottomLeft;
     //plot_par.line_or_points[3] = LineOrPoints::Points;
-    plot_par.font_family = format!("Times New Roman");
+    plot_par.font_family = "Times New Roman".to_string();
     plot_par.dashes = plot::DASHTYPES.to_vec();
     plot_par.custom_range_y = true;
     plot_par.range_y = [-1.0,1.0];
     plot_par.font_scale = 1.5;
     plot_par.line_scale = 1.5;
     //plot
-    plot::line_plot(&vec![x.clone(); 5], &vec![y1.clone(), y2.clone(), y3.clone(), y4.clone(), y5.clone()], &plot_par);
-    file::save_columns_to_file(&vec![x, y1, y2, y3, y4, y5], "results", "taylor.dat");
-
+    let (x, y) = function::function_plot(&functions, [x_min, x_max], &plot_par);
+    // x and f(x) columns for every function, adaptive grids differ in length
+    file::save_columns_to_file(x.iter().zip(&y).collect::<Vec<_>>(), "results", "taylor.dat");
+    plot::shutdown_kaleido();
 }
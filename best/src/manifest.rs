@@ -0,0 +1,571 @@
+// Figure manifests: the figures of a paper in one TOML file instead of a line_plot call each, e.g.
+//
+//     output = "figures"
+//
+//     [defaults]
+//     width = 1200
+//     formats = ["pdf", "png"]
+//     font_family = "Times"
+//
+//     [[figure]]
+//     name = "iv_curve"
+//     xlab = "E, V/cm"
+//     log_y = true
+//     data = [{ file = "results/IV.dat", columns = "x = 1e4*$1; y = $2", legends = ["j"] }]
+//     caption = "Current density at 300 K"
+//
+//     [report]
+//     file = "figures/report.html"
+//
+//     [pdf]
+//     file = "figures/supplement.pdf"
+//     page_size = "a4"
+//     landscape = true
+//
+// [defaults] takes every key of a figure but name, data and caption, and a figure's keys override it.
+// The report, html or Markdown by the file's extension, is written after every run, see report.rs;
+// for its interactive figures the json format is added to the formats of an html report, and png
+// to those of a figure without an image format in a Markdown report.
+// [pdf] collects the pdf of every figure into one file with the captions, see pdf.rs.
+// Paths are relative to the manifest. A figure is skipped when its entry, the defaults and its
+// data files are unchanged since it was last rendered, which is kept in <manifest>.state
+// together with its legends for the report.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::downsample::Downsample;
+use crate::pdf::{PageSize, PdfPages};
+use crate::plot::{line_plot, LegendAl, LineOrPoints, PlotPar};
+use crate::queue::RenderQueue;
+use crate::report::{Report, IMAGE_FORMATS};
+use crate::series::Series;
+use crate::spec::ColumnSpec;
+use crate::tokenizer::{read_columns_with, ParseOptions};
+use crate::watch::catch_panic;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    // directory of the figures, relative to the manifest [default: the manifest's directory]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub defaults: FigureEntry,
+    #[serde(default, rename = "figure")]
+    pub figures: Vec<FigureEntry>,
+    pub report: Option<ReportEntry>,
+    pub pdf: Option<PdfEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportEntry {
+    // .html, or .md for Markdown
+    pub file: String,
+    // [default: the manifest's name]
+    pub title: Option<String>,
+}
+
+// Keys of [[figure]] and [defaults]; unset style keys fall back to PlotPar::new
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FigureEntry {
+    // output file name without extension
+    pub name: Option<String>,
+    pub data: Vec<DataSource>,
+    // text under the figure in the report
+    pub caption: Option<String>,
+    pub title: Option<String>,
+    pub xlab: Option<String>,
+    pub ylab: Option<String>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub formats: Option<Vec<String>>,
+    pub offline_html: Option<bool>,
+    pub log_x: Option<bool>,
+    pub log_y: Option<bool>,
+    pub x_range: Option<[f64; 2]>,
+    pub y_range: Option<[f64; 2]>,
+    // names as in plotdat: "top-right", "line", "lttb" and so on
+    pub legend_pos: Option<String>,
+    pub show_legend: Option<bool>,
+    pub show_grid: Option<bool>,
+    pub style: Option<String>,
+    pub downsample: Option<String>,
+    pub font_family: Option<String>,
+    pub font_scale: Option<f64>,
+    pub line_scale: Option<f64>,
+}
+
+// Series of one data file; legends default to the y specs or column names
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataSource {
+    pub file: String,
+    // column spec, see spec.rs [default: x = $1, y = the others]
+    pub columns: Option<String>,
+    pub legends: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PdfEntry {
+    pub file: String,
+    // "a4", "letter", "figure" or "<width>x<height>" in points [default: a4]
+    pub page_size: Option<String>,
+    pub landscape: Option<bool>,
+    // in points [default: 36]
+    pub margin: Option<f64>,
+    pub caption_size: Option<f64>,
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Manifest, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let manifest: Manifest = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let defaults = &manifest.defaults;
+        if defaults.name.is_some() || !defaults.data.is_empty() || defaults.caption.is_some() {
+            return Err(format!("{}: name, data and caption can't be given in [defaults]", path.display()));
+        }
+        let mut names = Vec::new();
+        for (k, figure) in manifest.figures.iter().enumerate() {
+            match &figure.name {
+                None => return Err(format!("{}: figure {} has no name", path.display(), k + 1)),
+                Some(name) if names.contains(&name) => return Err(format!("{}: figure {} is given twice", path.display(), name)),
+                Some(name) => names.push(name),
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+impl FigureEntry {
+    // Settings in a TOML file of their own, the keys of [defaults], e.g. for plotdat --config
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FigureEntry, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let entry: FigureEntry = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if entry.name.is_some() || !entry.data.is_empty() || entry.caption.is_some() {
+            return Err(format!("{}: name, data and caption can't be given in settings", path.display()));
+        }
+        Ok(entry)
+    }
+
+    // self with the keys it doesn't set taken from defaults
+    pub fn or(&self, defaults: &FigureEntry) -> FigureEntry {
+        macro_rules! or {
+            ($($key:ident),*) => {
+                FigureEntry {
+                    name: self.name.clone(),
+                    data: self.data.clone(),
+                    caption: self.caption.clone(),
+                    $($key: self.$key.clone().or_else(|| defaults.$key.clone()),)*
+                }
+            };
+        }
+        or!(title, xlab, ylab, width, height, formats, offline_html, log_x, log_y, x_range, y_range, legend_pos,
+            show_legend, show_grid, style, downsample, font_family, font_scale, line_scale)
+    }
+
+    // PlotPar of the figure, its style keys over the defaults of PlotPar::new
+    pub fn plot_par(&self, flnm: &str, legends: Vec<String>, lines_number: usize) -> Result<PlotPar, String> {
+        let mut plot_par = PlotPar::new(1600, 1080, "x", "y", "", flnm, legends);
+        macro_rules! set {
+            ($($key:ident => $field:ident),*) => {
+                $(if let Some(value) = &self.$key {
+                    plot_par.$field = value.clone();
+                })*
+            };
+        }
+        set!(title => title, xlab => xlab, ylab => ylab, width => width, height => height, formats => formats,
+             offline_html => offline_html, log_x => log_x, log_y => log_y, show_legend => show_legend, show_grid => show_grid,
+             font_family => font_family, font_scale => font_scale, line_scale => line_scale);
+        if let Some(range) = self.x_range {
+            plot_par.custom_range_x = true;
+            plot_par.range_x = range;
+        }
+        if let Some(range) = self.y_range {
+            plot_par.custom_range_y = true;
+            plot_par.range_y = range;
+        }
+        if let Some(pos) = &self.legend_pos {
+            plot_par.legend_al = pos.parse::<LegendAl>()?;
+        }
+        if let Some(style) = &self.style {
+            plot_par.line_or_points = vec![style.parse::<LineOrPoints>()?; lines_number.max(100)];
+        }
+        if let Some(downsample) = &self.downsample {
+            plot_par.downsample = downsample.parse::<Downsample>()?;
+        }
+        Ok(plot_par)
+    }
+}
+
+pub struct BatchOptions {
+    // render every figure, changed or not
+    pub force: bool,
+    // names of the figures to render [default: all]
+    pub only: Vec<String>,
+    // skip a last line without a newline in the data files, see ParseOptions
+    pub complete_lines: bool,
+    // figures rendered at the same time, 0 for the number of CPUs
+    pub workers: usize,
+}
+
+impl BatchOptions {
+    pub fn new() -> BatchOptions {
+        BatchOptions {
+            force: false,
+            only: Vec::new(),
+            complete_lines: false,
+            workers: 0,
+        }
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FigureStatus {
+    Rendered,
+    Unchanged,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FigureResult {
+    pub name: String,
+    pub status: FigureStatus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResults {
+    // in the manifest's order
+    pub figures: Vec<FigureResult>,
+    // about the report and the pdf, e.g. caption characters that no installed font has
+    pub warnings: Vec<String>,
+}
+
+// Renders the figures of the manifest, several at a time, and returns their results.
+// A figure that fails doesn't stop the others, it's reported in its result;
+// Err is for a manifest that can't be read and a report or pdf that can't be written.
+pub fn render_manifest<P: AsRef<Path>>(path: P, options: &BatchOptions) -> Result<BatchResults, String> {
+    let path = path.as_ref();
+    let manifest = Manifest::load(path)?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let output = dir.join(manifest.output.as_deref().unwrap_or(""));
+    if !output.as_os_str().is_empty() {
+        fs::create_dir_all(&output).map_err(|e| format!("can't create {}: {}", output.display(), e))?;
+    }
+
+    let state_path = state_path(path);
+    let old_state: BTreeMap<String, Value> = fs::read_to_string(&state_path).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    // name, merged entry, output file name, state and whether it's rendered, for every figure
+    let mut prepared = Vec::new();
+    let html_report = manifest.report.as_ref().map(|r| r.file.ends_with(".html")).unwrap_or(false);
+    let markdown_report = manifest.report.is_some() && !html_report;
+    for figure in manifest.figures.iter() {
+        let name = figure.name.clone().unwrap_or_default();
+        let mut entry = figure.or(&manifest.defaults);
+        let flnm = output.join(&name).to_string_lossy().to_string();
+        // formats the report and the pdf are made of
+        let mut formats = entry.plot_par(&flnm, Vec::new(), 0).map(|p| p.formats).unwrap_or_default();
+        let mut needed = Vec::new();
+        if html_report {
+            needed.push("json");
+        }
+        if markdown_report && !formats.iter().any(|f| IMAGE_FORMATS.contains(&f.as_str())) {
+            needed.push("png");
+        }
+        if manifest.pdf.is_some() {
+            needed.push("pdf");
+        }
+        if !needed.is_empty() {
+            for format in needed {
+                if !formats.iter().any(|f| f == format) {
+                    formats.push(format.to_string());
+                }
+            }
+            entry.formats = Some(formats);
+        }
+        let stamp = serde_json::json!({
+            "entry": entry,
+            "output": flnm,
+            "inputs": entry.data.iter().map(|d| file_stamp(&dir.join(&d.file))).collect::<Vec<_>>(),
+        });
+        let selected = options.only.is_empty() || options.only.contains(&name);
+        let up_to_date = old_state.get(&name).and_then(|old| old.get("stamp")) == Some(&stamp) && entry.plot_par(&flnm, Vec::new(), 0)
+            .map(|p| p.formats.iter().all(|format| PathBuf::from(&flnm).with_extension(format).exists()))
+            .unwrap_or(false);
+        let render = selected && (options.force || !up_to_date);
+        prepared.push((name, entry, flnm, stamp, selected, render));
+    }
+
+    // legends of the rendered figures, for the report
+    let legends = Mutex::new(BTreeMap::new());
+    let mut queue = RenderQueue::new();
+    if options.workers > 0 {
+        queue.workers = options.workers;
+    }
+    for (name, entry, flnm, _, _, render) in prepared.iter() {
+        if *render {
+            let (dir, legends) = (&dir, &legends);
+            queue.add(name, move || {
+                let figure_legends = render_figure(entry, dir, flnm, options)?;
+                legends.lock().unwrap_or_else(|e| e.into_inner()).insert(name.clone(), figure_legends);
+                Ok(())
+            });
+        }
+    }
+    let mut rendered = queue.run().into_iter();
+    let mut legends: BTreeMap<String, Vec<String>> = legends.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    let mut state = BTreeMap::new();
+    let mut results = Vec::new();
+    for (name, _, _, stamp, selected, render) in prepared.iter() {
+        let name = name.clone();
+        let status = if *render {
+            match rendered.next().map(|(_, result)| result) {
+                Some(Ok(())) => {
+                    let legends = legends.remove(&name).unwrap_or_default();
+                    state.insert(name.clone(), serde_json::json!({ "stamp": stamp, "legends": legends }));
+                    FigureStatus::Rendered
+                },
+                Some(Err(message)) => FigureStatus::Failed(message),
+                None => FigureStatus::Failed("not rendered".to_string()),
+            }
+        } else {
+            if let Some(old) = old_state.get(&name) {
+                state.insert(name.clone(), old.clone());
+            }
+            FigureStatus::Unchanged
+        };
+        if *selected {
+            results.push(FigureResult { name, status });
+        }
+    }
+
+    let text = serde_json::to_string_pretty(&state).expect("Can't convert figure state to JSON");
+    fs::write(&state_path, text).map_err(|e| format!("can't write {}: {}", state_path.display(), e))?;
+
+    if let Some(report) = &manifest.report {
+        let title = report.title.clone()
+            .unwrap_or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default());
+        let file = dir.join(&report.file);
+        catch_panic(|| {
+            let mut r = Report::new(&title);
+            // figures rendered now or before, with the legends of their state;
+            // failed ones have no state and are left out
+            for (name, entry, flnm, _, _, _) in prepared.iter() {
+                let Some(figure_state) = state.get(name) else { continue };
+                let data_files: Vec<&str> = entry.data.iter().map(|d| d.file.as_str()).collect();
+                let legends: Vec<String> = serde_json::from_value(figure_state["legends"].clone()).unwrap_or_default();
+                let lines_number = legends.len();
+                r.add(&entry.plot_par(flnm, legends, lines_number)?, entry.caption.as_deref().unwrap_or(""), &data_files);
+            }
+            if html_report { r.write_html(&file) } else { r.write_markdown(&file) }
+        }).map_err(|e| format!("report {}: {}", file.display(), e))?;
+    }
+    let mut warnings = Vec::new();
+    if let Some(pdf) = &manifest.pdf {
+        let file = dir.join(&pdf.file);
+        catch_panic(|| {
+            let mut pages = PdfPages::new();
+            if let Some(size) = &pdf.page_size {
+                pages.options.page_size = size.parse::<PageSize>()?;
+            }
+            pages.options.landscape = pdf.landscape.unwrap_or(pages.options.landscape);
+            pages.options.margin = pdf.margin.unwrap_or(pages.options.margin);
+            pages.options.caption_size = pdf.caption_size.unwrap_or(pages.options.caption_size);
+            for (_, entry, flnm, _, _, _) in prepared.iter().filter(|f| state.contains_key(&f.0)) {
+                pages.add_file(PathBuf::from(flnm).with_extension("pdf"), entry.caption.as_deref().unwrap_or(""));
+            }
+            warnings.extend(pages.write(&file)?.into_iter().map(|w| format!("pdf {}: {}", file.display(), w)));
+            Ok(())
+        }).map_err(|e| format!("pdf {}: {}", file.display(), e))?;
+    }
+    Ok(BatchResults { figures: results, warnings })
+}
+
+// The manifest and every data file it names, for watching
+pub fn manifest_inputs<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, String> {
+    let path = path.as_ref();
+    let manifest = Manifest::load(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut inputs = vec![path.to_path_buf()];
+    for source in manifest.figures.iter().flat_map(|f| f.data.iter()) {
+        let file = dir.join(&source.file);
+        if !inputs.contains(&file) {
+            inputs.push(file);
+        }
+    }
+    Ok(inputs)
+}
+
+// Plots the figure and returns its legends
+fn render_figure(entry: &FigureEntry, dir: &Path, flnm: &str, options: &BatchOptions) -> Result<Vec<String>, String> {
+    let (data, legends) = load_figure(entry, dir, options)?;
+    let mut series = Series::empty();
+    for (x, ys) in data.iter() {
+        series.push_shared_x(x, ys);
+    }
+    let plot_par = entry.plot_par(flnm, legends.clone(), series.len())?;
+    line_plot(series, &plot_par);
+    Ok(legends)
+}
+
+// x and the y against it of every data file
+type FigureData = Vec<(Vec<f64>, Vec<Vec<f64>>)>;
+
+// The series of the figure and its legends: the given ones, then the y specs or column names,
+// after the file's name when the figure has several files
+fn load_figure(entry: &FigureEntry, dir: &Path, options: &BatchOptions) -> Result<(FigureData, Vec<String>), String> {
+    let mut parse_options = ParseOptions::new();
+    parse_options.complete_lines = options.complete_lines;
+
+    let mut data = Vec::new();
+    let mut legends = Vec::new();
+    for source in entry.data.iter() {
+        let path = dir.join(&source.file);
+        let spec = ColumnSpec::parse(source.columns.as_deref().unwrap_or("")).map_err(|e| format!("{}: {}", source.file, e))?;
+        let (names, columns) = read_columns_with(&path, &parse_options).map_err(|e| format!("{}: {}", source.file, e))?;
+        let (x, ys) = spec.apply(&names, &columns).map_err(|e| format!("{}: {}", source.file, e))?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let labels = spec.y_labels(&names, columns.len()).into_iter()
+            .map(|label| if entry.data.len() > 1 { format!("{}: {}", stem, label) } else { label });
+        let mut given = source.legends.clone();
+        given.extend(labels.skip(source.legends.len()));
+        legends.extend(given.into_iter().take(ys.len()));
+        if !ys.is_empty() {
+            data.push((x, ys));
+        }
+    }
+    if data.is_empty() {
+        return Err("no data".to_string());
+    }
+    Ok((data, legends))
+}
+
+// Modification time and size, null for a missing file
+fn file_stamp(path: &Path) -> Value {
+    match fs::metadata(path) {
+        Ok(m) => {
+            let modified = m.modified().ok()
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            serde_json::json!([modified.as_secs(), modified.subsec_nanos(), m.len()])
+        },
+        Err(_) => Value::Null,
+    }
+}
+
+fn state_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".state");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory with data files and figures.toml, figures in out/ as json, which needs no kaleido
+    fn setup(name: &str, figures: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("manifest-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.dat"), "1 2 3\n2 4 6\n").unwrap();
+        fs::write(dir.join("b.dat"), "0 1\n1 0\n").unwrap();
+        let manifest = format!("output = \"out\"\n[defaults]\nformats = [\"json\"]\n{}", figures);
+        fs::write(dir.join("figures.toml"), manifest).unwrap();
+        dir.join("figures.toml")
+    }
+
+    fn statuses(path: &Path, options: &BatchOptions) -> Vec<FigureStatus> {
+        render_manifest(path, options).unwrap().figures.into_iter().map(|r| r.status).collect()
+    }
+
+    #[test]
+    fn skips_unchanged() {
+        let path = setup("skip", "[[figure]]\nname = \"a\"\ndata = [{ file = \"a.dat\" }, { file = \"b.dat\" }]\n");
+        let dir = path.parent().unwrap();
+        let options = BatchOptions::new();
+        assert_eq!(statuses(&path, &options), vec![FigureStatus::Rendered]);
+        let json = fs::read_to_string(dir.join("out/a.json")).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap()["data"].as_array().unwrap().len(), 3);
+        assert_eq!(statuses(&path, &options), vec![FigureStatus::Unchanged]);
+
+        // changed data, a deleted output and force render again
+        fs::write(dir.join("b.dat"), "0 1\n1 0\n2 1\n").unwrap();
+        assert_eq!(statuses(&path, &options), vec![FigureStatus::Rendered]);
+        fs::remove_file(dir.join("out/a.json")).unwrap();
+        assert_eq!(statuses(&path, &options), vec![FigureStatus::Rendered]);
+        let mut force = BatchOptions::new();
+        force.force = true;
+        assert_eq!(statuses(&path, &force), vec![FigureStatus::Rendered]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn report_legends() {
+        let figures = "[[figure]]\nname = \"a\"\ndata = [{ file = \"a.dat\", legends = [\"first\"] }]\n\
+                       [report]\nfile = \"report.html\"\n";
+        let path = setup("report", figures);
+        let dir = path.parent().unwrap();
+        let options = BatchOptions::new();
+        assert_eq!(statuses(&path, &options), vec![FigureStatus::Rendered]);
+        let state: Value = serde_json::from_str(&fs::read_to_string(state_path(&path)).unwrap()).unwrap();
+        assert_eq!(state["a"]["legends"], serde_json::json!(["first", "$3"]));
+        // the legends of an unchanged figure come from the state, not from its data
+        fs::remove_file(dir.join("report.html")).unwrap();
+        assert_eq!(statuses(&path, &options), vec![FigureStatus::Unchanged]);
+        assert!(fs::read_to_string(dir.join("report.html")).unwrap().contains("first; $3"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_figures() {
+        let figures = "[[figure]]\nname = \"a\"\ndata = [{ file = \"a.dat\" }]\n\
+                       [[figure]]\nname = \"missing\"\ndata = [{ file = \"missing.dat\" }]\n\
+                       [[figure]]\nname = \"bad\"\ndata = [{ file = \"a.dat\", columns = \"y = $9\" }]\n";
+        let path = setup("failed", figures);
+        let options = BatchOptions::new();
+        let results = statuses(&path, &options);
+        assert_eq!(results[0], FigureStatus::Rendered);
+        assert!(matches!(&results[1], FigureStatus::Failed(message) if message.contains("missing.dat")));
+        assert!(matches!(results[2], FigureStatus::Failed(_)));
+        // failed figures have no state and are tried again
+        let results = statuses(&path, &options);
+        assert_eq!(results[0], FigureStatus::Unchanged);
+        assert!(matches!(results[1], FigureStatus::Failed(_)));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn invalid_manifests() {
+        let invalid = [
+            ("unnamed", "[[figure]]\ndata = [{ file = \"a.dat\" }]\n"),
+            ("twice", "[[figure]]\nname = \"a\"\n[[figure]]\nname = \"a\"\n"),
+            ("unknown", "[[figure]]\nname = \"a\"\ncolour = \"red\"\n"),
+            // still in [defaults]
+            ("defaults", "caption = \"text\"\n"),
+        ];
+        for (name, figures) in invalid {
+            let path = setup(name, figures);
+            assert!(render_manifest(&path, &BatchOptions::new()).is_err(), "{}", name);
+            fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+        assert!(render_manifest("/nonexistent/figures.toml", &BatchOptions::new()).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+// NumPy .npy and .npz files of columns, keeping full f64 precision.
+// np.load of a .npy file gives the same rows x columns array as np.loadtxt of the text file.
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::series::IntoSeries;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+// One 2D little endian f64 array, shorter columns padded with NaN
+pub fn save_columns_to_npy<'a, S: IntoSeries<'a>>(series: S, dir_name: &str, file_name: &str) {
+    let series = series.into_series();
+    let columns = series.columns();
+    let rows = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+    let mut data = Vec::with_capacity(rows * columns.len());
+    for i in 0..rows {
+        data.extend(columns.iter().map(|c| c.get(i).cloned().unwrap_or(f64::NAN)));
+    }
+    let mut file = BufWriter::new(create_file(dir_name, file_name));
+    write_npy(&mut file, &data, &format!("({}, {})", rows, columns.len()))
+        .unwrap_or_else(|_| panic!("Can't write {}", file_name));
+}
+
+// Every column as its own 1D array, np.load(file)[name]. Columns without a name are arr_0, arr_1, ...
+pub fn save_columns_to_npz<'a, S: IntoSeries<'a>>(series: S, names: &[&str], dir_name: &str, file_name: &str) {
+    let series = series.into_series();
+    let mut zip = ZipWriter::new(create_file(dir_name, file_name));
+    // np.savez stores the arrays uncompressed
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for (k, column) in series.columns().into_iter().enumerate() {
+        let name = names.get(k).map(|name| name.to_string()).unwrap_or_else(|| format!("arr_{}", k));
+        zip.start_file(format!("{}.npy", name), options)
+            .and_then(|_| write_npy(&mut zip, column, &format!("({},)", column.len())).map_err(Into::into))
+            .unwrap_or_else(|_| panic!("Can't write {} to {}", name, file_name));
+    }
+    zip.finish().unwrap_or_else(|_| panic!("Can't write {}", file_name));
+}
+
+// Columns of a 1D or 2D array of floats or integers, in either byte order and memory layout.
+// A file that isn't such an array is an InvalidData error.
+pub fn read_npy<P: AsRef<Path>>(path: P) -> io::Result<Vec<Vec<f64>>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    parse_npy(&bytes).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+// Names and columns of all arrays, a 2D array named a gives the columns a_1, a_2, ...
+pub fn read_npz<P: AsRef<Path>>(path: P) -> io::Result<(Vec<String>, Vec<Vec<f64>>)> {
+    let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+    let mut zip = ZipArchive::new(File::open(path)?).map_err(|e| invalid(format!("not an npz archive: {}", e)))?;
+    let mut names = Vec::new();
+    let mut columns = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| invalid(e.to_string()))?;
+        let name = entry.name().trim_end_matches(".npy").to_string();
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        let array = parse_npy(&bytes).map_err(|e| invalid(format!("{}: {}", name, e)))?;
+        if array.len() == 1 {
+            names.push(name);
+        } else {
+            names.extend((1..=array.len()).map(|k| format!("{}_{}", name, k)));
+        }
+        columns.extend(array);
+    }
+    Ok((names, columns))
+}
+
+pub(crate) fn create_file(dir_name: &str, file_name: &str) -> File {
+    let _ = std::fs::create_dir(dir_name);
+    let file_path: PathBuf = [dir_name, file_name].iter().collect();
+    File::create(file_path).expect("Error creating file")
+}
+
+// Format version 1.0, the header padded so that the data starts at a multiple of 64 bytes
+fn write_npy<W: Write>(w: &mut W, data: &[f64], shape: &str) -> std::io::Result<()> {
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}", shape);
+    let total = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - total % 64) % 64));
+    header.push('\n');
+    w.write_all(MAGIC)?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    for value in data {
+        w.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn parse_npy(bytes: &[u8]) -> Result<Vec<Vec<f64>>, String> {
+    if bytes.len() < 10 || &bytes[..6] != MAGIC {
+        return Err("not an npy file".to_string());
+    }
+    let (header_start, header_len) = match bytes[6] {
+        1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+        2 | 3 if bytes.len() >= 12 => (12, u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize),
+        version => return Err(format!("unsupported npy version {}", version)),
+    };
+    let header = bytes.get(header_start..header_start + header_len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or("truncated header")?;
+    let data = &bytes[header_start + header_len..];
+
+    let descr = dict_value(header, "descr").ok_or("no descr in header")?;
+    let descr = descr.trim_matches(|c| c == '\'' || c == '"');
+    let fortran_order = dict_value(header, "fortran_order").ok_or("no fortran_order in header")? == "True";
+    let shape: Vec<usize> = dict_value(header, "shape").ok_or("no shape in header")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|_| format!("invalid shape entry {}", s)))
+        .collect::<Result<_, _>>()?;
+    let (rows, cols) = match shape[..] {
+        [] => (1, 1),
+        [rows] => (rows, 1),
+        [rows, cols] => (rows, cols),
+        _ => return Err(format!("{}D arrays are not supported", shape.len())),
+    };
+
+    let n = rows.checked_mul(cols).ok_or("shape is too large")?;
+    let values = decode(descr, data, n)?;
+    Ok((0..cols).map(|j| (0..rows)
+        .map(|i| if fortran_order { values[j * rows + i] } else { values[i * cols + j] })
+        .collect()).collect())
+}
+
+// Value of key in the header dict, up to the next top level comma
+fn dict_value<'h>(header: &'h str, key: &str) -> Option<&'h str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') { rest.find(')')? + 1 } else { rest.find([',', '}'])? };
+    Some(rest[..end].trim())
+}
+
+fn decode(descr: &str, data: &[u8], n: usize) -> Result<Vec<f64>, String> {
+    let unsupported = || format!("unsupported dtype {:?}", descr);
+    let (order, kind) = descr.get(..1).zip(descr.get(1..)).ok_or_else(unsupported)?;
+    let big = match order {
+        ">" => true,
+        "<" | "|" => false,
+        "=" => cfg!(target_endian = "big"),
+        _ => return Err(unsupported()),
+    };
+    let size: usize = kind.get(1..).and_then(|size| size.parse().ok()).ok_or_else(unsupported)?;
+    if n.checked_mul(size).map(|len| data.len() < len).unwrap_or(true) {
+        return Err(format!("{} values expected, file is truncated", n));
+    }
+    macro_rules! convert {
+        ($t:ty) => {
+            data[..n * size].chunks_exact(size).map(|b| {
+                let b = b.try_into().unwrap();
+                (if big { <$t>::from_be_bytes(b) } else { <$t>::from_le_bytes(b) }) as f64
+            }).collect()
+        };
+    }
+    Ok(match kind {
+        "f8" => convert!(f64),
+        "f4" => convert!(f32),
+        "i8" => convert!(i64),
+        "i4" => convert!(i32),
+        "i2" => convert!(i16),
+        "i1" => convert!(i8),
+        "u8" => convert!(u64),
+        "u4" => convert!(u32),
+        "u2" => convert!(u16),
+        "u1" | "b1" => convert!(u8),
+        _ => return Err(unsupported()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        npy_in_order(descr, "False", shape, data)
+    }
+
+    fn npy_in_order(descr: &str, fortran_order: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let header = format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}\n", descr, fortran_order, shape);
+        bytes.extend(MAGIC);
+        bytes.extend([1, 0]);
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let dir = std::env::temp_dir().join(format!("npy-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let x = vec![1.0, 2.0, 3.0];
+        let y = vec![0.1, f64::MAX];
+        save_columns_to_npy((&x, &y), dir, "xy.npy");
+        let columns = read_npy(Path::new(dir).join("xy.npy")).unwrap();
+        save_columns_to_npz((&x, &y), &["t"], dir, "xy.npz");
+        let (names, npz_columns) = read_npz(Path::new(dir).join("xy.npz")).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(columns[0], x);
+        assert_eq!(columns[1][..2], y[..]);
+        assert!(columns[1][2].is_nan());
+        assert_eq!(names, vec!["t", "arr_1"]);
+        assert_eq!(npz_columns, vec![x, y]);
+    }
+
+    #[test]
+    fn dtypes_and_layouts() {
+        let data: Vec<u8> = [1i32, 2, 3, 4, 5, 6].iter().flat_map(|v| v.to_be_bytes()).collect();
+        assert_eq!(parse_npy(&npy(">i4", "(3, 2)", &data)).unwrap(), vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]]);
+        assert_eq!(parse_npy(&npy_in_order(">i4", "True", "(3, 2)", &data)).unwrap(), vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+        assert_eq!(parse_npy(&npy("|u1", "()", &[7])).unwrap(), vec![vec![7.0]]);
+    }
+
+    #[test]
+    fn bad_files() {
+        for descr in ["", "<", "<f", "<fx", "<c16", "?f8"] {
+            let error = parse_npy(&npy(descr, "(1,)", &[0; 16])).unwrap_err();
+            assert!(error.starts_with("unsupported dtype"), "{}: {}", descr, error);
+        }
+        assert_eq!(parse_npy(&npy("<f8", "(3,)", &[0; 16])).unwrap_err(), "3 values expected, file is truncated");
+        assert!(parse_npy(&npy("<f8", "(4294967296, 4294967296)", &[])).is_err());
+        assert!(parse_npy(&npy("<f8", "(1, 1, 1)", &[0; 8])).unwrap_err().contains("3D"));
+        assert_eq!(parse_npy(b"\x93NUMPX\x01\x00").unwrap_err(), "not an npy file");
+
+        assert_eq!(read_npy("/nonexistent/data.npy").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(read_npz("/nonexistent/data.npz").unwrap_err().kind(), ErrorKind::NotFound);
+        let path = std::env::temp_dir().join(format!("npy-bad-{}.npz", std::process::id()));
+        std::fs::write(&path, npy("<f8", "(1,)", &[0; 8])).unwrap();
+        assert_eq!(read_npy(&path).unwrap(), vec![vec![0.0]]);
+        assert_eq!(read_npz(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
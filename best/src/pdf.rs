@@ -0,0 +1,450 @@
+// Several figures in one pdf: the first page of every figure's pdf, as kaleido wrote it, is
+// placed on a page of its own as a form XObject, scaled to fit inside the margins, with an
+// optional caption below in Helvetica. Captions with characters outside WinAnsiEncoding, such
+// as the Δ of Δt, are in an installed TrueType font embedded into the pdf instead.
+// The figures stay vector graphics.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::fonts::{caption_font, TrueType};
+use crate::plot::PlotPar;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageSize {
+    A4,
+    Letter,
+    // every page as large as its figure, plus the margins and the caption
+    Figure,
+    // width and height in points
+    Points(f64, f64),
+}
+
+// "a4", "letter", "figure" or width x height in points such as "500x400"
+impl FromStr for PageSize {
+    type Err = String;
+    fn from_str(s: &str) -> Result<PageSize, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "a4" => PageSize::A4,
+            "letter" => PageSize::Letter,
+            "figure" => PageSize::Figure,
+            other => {
+                let size = other.split_once('x').and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+                match size {
+                    Some((w, h)) if w > 0.0 && h > 0.0 => PageSize::Points(w, h),
+                    _ => return Err(format!("unknown page size {:?}", s)),
+                }
+            },
+        })
+    }
+}
+
+pub struct PdfOptions {
+    pub page_size: PageSize,
+    // A4 and Letter pages turned sideways, which suits the usual wide figures
+    pub landscape: bool,
+    // in points, on every side
+    pub margin: f64,
+    // font size of the captions in points
+    pub caption_size: f64,
+}
+
+impl PdfOptions {
+    pub fn new() -> PdfOptions {
+        PdfOptions {
+            page_size: PageSize::A4,
+            landscape: false,
+            margin: 36.0,
+            caption_size: 10.0,
+        }
+    }
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct PdfPages {
+    pub options: PdfOptions,
+    figures: Vec<(PathBuf, String)>,
+}
+
+impl PdfPages {
+    pub fn new() -> PdfPages {
+        PdfPages {
+            options: PdfOptions::new(),
+            figures: Vec::new(),
+        }
+    }
+
+    // The flnm.pdf of a figure plotted with plot_par, read when the pdf is written
+    pub fn add(&mut self, plot_par: &PlotPar, caption: &str) {
+        self.add_file(PathBuf::from(&plot_par.flnm).with_extension("pdf"), caption);
+    }
+
+    pub fn add_file<P: AsRef<Path>>(&mut self, pdf: P, caption: &str) {
+        self.figures.push((pdf.as_ref().to_path_buf(), caption.to_string()));
+    }
+
+    // Writes the pdf and returns its warnings, e.g. about caption characters that no installed
+    // font has, which are written as ?; an error for a figure pdf it can't read or a failed write
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>, String> {
+        let path = path.as_ref();
+        let mut warnings = Vec::new();
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let captions: String = self.figures.iter().map(|(_, caption)| caption.as_str()).collect::<Vec<_>>().join(" ");
+        let mut missing: Vec<char> = captions.chars().filter(|&c| !c.is_control() && win_ansi(c).is_none()).collect();
+        missing.sort();
+        missing.dedup();
+        let font = if missing.is_empty() {
+            CaptionFont::Helvetica
+        } else if let Some(font) = caption_font(&captions) {
+            CaptionFont::TrueType(font)
+        } else {
+            let missing: String = missing.into_iter().collect();
+            warnings.push(format!("no installed TrueType font has all of {:?}, they are ? in the captions", missing));
+            CaptionFont::Helvetica
+        };
+        let font_id = font.add_to(&mut doc, &captions);
+
+        let mut kids = Vec::new();
+        for (pdf, caption) in self.figures.iter() {
+            let (form_id, bbox) = import_first_page(&mut doc, pdf)?;
+            let (content, media_box) = self.page_content(bbox, caption, &font);
+            let content_id = doc.add_object(Stream::new(Dictionary::new(), content));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => media_box.iter().map(|&v| Object::Real(v as f32)).collect::<Vec<_>>(),
+                "Contents" => content_id,
+                "Resources" => dictionary! {
+                    "XObject" => dictionary! { "Figure" => form_id },
+                    "Font" => dictionary! { "Caption" => font_id },
+                },
+            });
+            kids.push(Object::Reference(page_id));
+        }
+        let count = kids.len() as i64;
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        }));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        // the catalogs and page trees of the figures' files are left behind
+        doc.prune_objects();
+        doc.compress();
+        doc.save(path).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+        Ok(warnings)
+    }
+
+    // Content stream of a page with the figure and its caption, and the page's MediaBox
+    fn page_content(&self, bbox: [f64; 4], caption: &str, font: &CaptionFont) -> (Vec<u8>, [f64; 4]) {
+        let options = &self.options;
+        let (fig_w, fig_h) = (bbox[2] - bbox[0], bbox[3] - bbox[1]);
+        let size = options.caption_size;
+        let leading = 1.25 * size;
+        let margin = options.margin;
+
+        let (page_w, page_h) = match options.page_size {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::Points(w, h) => (w, h),
+            PageSize::Figure => (fig_w + 2.0 * margin, 0.0),
+        };
+        let (page_w, page_h) = if options.landscape && matches!(options.page_size, PageSize::A4 | PageSize::Letter) {
+            (page_h, page_w)
+        } else {
+            (page_w, page_h)
+        };
+        let lines = wrap(caption, page_w - 2.0 * margin, size, font);
+        let caption_h = if lines.is_empty() { 0.0 } else { size + leading * lines.len() as f64 };
+        let page_h = if options.page_size == PageSize::Figure { fig_h + 2.0 * margin + caption_h } else { page_h };
+
+        let scale = ((page_w - 2.0 * margin) / fig_w).min((page_h - 2.0 * margin - caption_h) / fig_h).max(0.0);
+        let x = margin + (page_w - 2.0 * margin - scale * fig_w) / 2.0 - scale * bbox[0];
+        let top = page_h - margin;
+        let y = top - scale * fig_h - scale * bbox[1];
+
+        let mut content = format!("q {} 0 0 {} {} {} cm /Figure Do Q\n", scale, scale, x, y).into_bytes();
+        if !lines.is_empty() {
+            let first = top - scale * fig_h - size - size;
+            content.extend(format!("BT /Caption {} Tf {} TL {} {} Td\n", size, leading, margin, first).bytes());
+            for line in lines {
+                content.extend(font.encode(&line));
+                content.extend(b" Tj T*\n");
+            }
+            content.extend(b"ET\n");
+        }
+        (content, [0.0, 0.0, page_w, page_h])
+    }
+}
+
+// Helvetica, which every reader has, or a TrueType font addressed by glyph ids
+enum CaptionFont {
+    Helvetica,
+    TrueType(TrueType),
+}
+
+impl CaptionFont {
+    // The string operand of Tj
+    fn encode(&self, text: &str) -> Vec<u8> {
+        let text = text.chars().map(|c| if c.is_control() { ' ' } else { c });
+        match self {
+            CaptionFont::Helvetica => {
+                let mut bytes = vec![b'('];
+                for c in text {
+                    match win_ansi(c) {
+                        Some(b) if b"()\\".contains(&b) => bytes.extend([b'\\', b]),
+                        Some(b) => bytes.push(b),
+                        None => bytes.push(b'?'),
+                    }
+                }
+                bytes.push(b')');
+                bytes
+            },
+            CaptionFont::TrueType(font) => {
+                let glyphs: Vec<String> = text.map(|c| format!("{:04X}", font.glyph(c).unwrap_or(0))).collect();
+                format!("<{}>", glyphs.concat()).into_bytes()
+            },
+        }
+    }
+
+    // In points, with the widths of the characters as encode writes them
+    fn width(&self, text: &str, size: f64) -> f64 {
+        match self {
+            CaptionFont::Helvetica => {
+                let units: u32 = text.chars()
+                    .map(|c| if c.is_control() { b' ' } else { win_ansi(c).unwrap_or(b'?') })
+                    .map(|b| HELVETICA_WIDTHS[b as usize - 0x20] as u32)
+                    .sum();
+                size * units as f64 / 1000.0
+            },
+            CaptionFont::TrueType(font) => {
+                let units: f64 = text.chars().map(|c| font.advance(font.glyph(c).unwrap_or(0))).sum();
+                size * units / font.units_per_em
+            },
+        }
+    }
+
+    // The font dictionary, with the font file and the widths of the glyphs of text
+    fn add_to(&self, doc: &mut Document, text: &str) -> ObjectId {
+        let font = match self {
+            CaptionFont::Helvetica => return doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => "WinAnsiEncoding",
+            }),
+            CaptionFont::TrueType(font) => font,
+        };
+        let scale = |v: f64| Object::Real((1000.0 * v / font.units_per_em) as f32);
+        let glyphs: BTreeMap<u16, char> = text.chars().chain([' ']).filter_map(|c| Some((font.glyph(c)?, c))).collect();
+
+        let mut file = Stream::new(dictionary! { "Length1" => font.data.len() as i64 }, font.data.clone());
+        let _ = file.compress();
+        let file_id = doc.add_object(file);
+        let descriptor_id = doc.add_object(dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => font.name.as_str(),
+            // nonsymbolic
+            "Flags" => 32,
+            "FontBBox" => font.bbox.iter().map(|&v| scale(v)).collect::<Vec<_>>(),
+            "ItalicAngle" => 0,
+            "Ascent" => scale(font.ascent),
+            "Descent" => scale(font.descent),
+            "CapHeight" => scale(font.ascent),
+            "StemV" => 80,
+            "FontFile2" => file_id,
+        });
+        let widths: Vec<Object> = glyphs.keys()
+            .flat_map(|&glyph| [Object::Integer(glyph as i64), Object::Array(vec![scale(font.advance(glyph))])])
+            .collect();
+        let cid_font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "CIDFontType2",
+            "BaseFont" => font.name.as_str(),
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => descriptor_id,
+            "W" => widths,
+            "CIDToGIDMap" => "Identity",
+        });
+
+        // glyph ids back to text, for copying and searching
+        let mut cmap = String::from("/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+            /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+            /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+            1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n");
+        let glyphs: Vec<(&u16, &char)> = glyphs.iter().collect();
+        for chunk in glyphs.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+            for (glyph, c) in chunk {
+                let utf16: String = c.encode_utf16(&mut [0; 2]).iter().map(|u| format!("{:04X}", u)).collect();
+                cmap.push_str(&format!("<{:04X}> <{}>\n", glyph, utf16));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+        cmap.push_str("endcmap\nCMapName currentdict /CMapResource defineresource pop\nend\nend\n");
+        let to_unicode_id = doc.add_object(Stream::new(Dictionary::new(), cmap.into_bytes()));
+
+        doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => font.name.as_str(),
+            "Encoding" => "Identity-H",
+            "DescendantFonts" => vec![Object::Reference(cid_font_id)],
+            "ToUnicode" => to_unicode_id,
+        })
+    }
+}
+
+impl Default for PdfPages {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Moves the objects of pdf into doc and returns a form XObject of its first page with the page's MediaBox
+fn import_first_page(doc: &mut Document, pdf: &Path) -> Result<(ObjectId, [f64; 4]), String> {
+    let mut source = Document::load(pdf).map_err(|e| format!("can't read {}: {}", pdf.display(), e))?;
+    source.renumber_objects_with(doc.max_id + 1);
+    let page_id = *source.get_pages().get(&1).ok_or_else(|| format!("{} has no pages", pdf.display()))?;
+    let content = source.get_page_content(page_id).map_err(|e| format!("can't read the page of {}: {}", pdf.display(), e))?;
+
+    // Resources and MediaBox may be inherited from the page tree
+    let inherited = |key: &[u8]| {
+        let mut node = source.get_dictionary(page_id).ok();
+        while let Some(dict) = node {
+            if let Ok(value) = dict.get(key) {
+                return Some(value.clone());
+            }
+            node = dict.get(b"Parent").and_then(Object::as_reference).and_then(|id| source.get_dictionary(id)).ok();
+        }
+        None
+    };
+    let resources = inherited(b"Resources").unwrap_or_else(|| Object::Dictionary(Dictionary::new()));
+    let media_box = inherited(b"MediaBox")
+        .and_then(|b| match b {
+            Object::Reference(id) => source.get_object(id).ok().cloned(),
+            b => Some(b),
+        })
+        .and_then(|b| b.as_array().ok().map(|a| a.iter().filter_map(|v| v.as_float().ok()).map(|v| v as f64).collect::<Vec<_>>()))
+        .filter(|b| b.len() == 4)
+        .map(|b| [b[0], b[1], b[2], b[3]])
+        .ok_or_else(|| format!("the page of {} has no MediaBox", pdf.display()))?;
+
+    doc.max_id = doc.max_id.max(source.max_id);
+    doc.objects.extend(source.objects);
+    let form = Stream::new(dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => media_box.iter().map(|&v| Object::Real(v as f32)).collect::<Vec<_>>(),
+        "Resources" => resources,
+    }, content);
+    Ok((doc.add_object(form), media_box))
+}
+
+// Caption lines no wider than width, as far as the words allow
+fn wrap(text: &str, width: f64, size: f64, font: &CaptionFont) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if font.width(&format!("{} {}", line, word), size) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            },
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+// Advance widths of Helvetica in 1/1000 of the font size from its standard AFM metrics,
+// for the WinAnsiEncoding bytes 0x20 to 0xff; the unused bytes have the width of the bullet
+const HELVETICA_WIDTHS: [u16; 224] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, 350,
+    556, 350, 222, 556, 333, 1000, 556, 556, 333, 1000, 667, 333, 1000, 350, 611, 350,
+    350, 222, 222, 333, 333, 350, 556, 1000, 333, 1000, 500, 333, 944, 350, 500, 667,
+    278, 333, 556, 556, 556, 556, 260, 556, 333, 737, 370, 556, 584, 333, 737, 333,
+    400, 584, 333, 333, 333, 556, 537, 278, 333, 333, 365, 556, 834, 834, 834, 611,
+    667, 667, 667, 667, 667, 667, 1000, 722, 667, 667, 667, 667, 278, 278, 278, 278,
+    722, 722, 778, 778, 778, 778, 778, 584, 778, 722, 722, 722, 722, 667, 667, 611,
+    556, 556, 556, 556, 556, 556, 889, 500, 556, 556, 556, 556, 278, 278, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 584, 611, 556, 556, 556, 556, 500, 556, 500,
+];
+
+// The byte of c in WinAnsiEncoding: Latin-1, and typographic quotes, dashes and a few more in 0x80 to 0x9f
+fn win_ansi(c: char) -> Option<u8> {
+    const EXTRA: [(char, u8); 27] = [
+        ('€', 0x80), ('‚', 0x82), ('ƒ', 0x83), ('„', 0x84), ('…', 0x85), ('†', 0x86), ('‡', 0x87),
+        ('ˆ', 0x88), ('‰', 0x89), ('Š', 0x8a), ('‹', 0x8b), ('Œ', 0x8c), ('Ž', 0x8e), ('‘', 0x91),
+        ('’', 0x92), ('“', 0x93), ('”', 0x94), ('•', 0x95), ('–', 0x96), ('—', 0x97), ('˜', 0x98),
+        ('™', 0x99), ('š', 0x9a), ('›', 0x9b), ('œ', 0x9c), ('ž', 0x9e), ('Ÿ', 0x9f),
+    ];
+    match c as u32 {
+        0x20..=0x7e | 0xa0..=0xff => Some(c as u32 as u8),
+        _ => EXTRA.iter().find(|(extra, _)| *extra == c).map(|(_, b)| *b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helvetica_strings() {
+        let font = CaptionFont::Helvetica;
+        assert_eq!(font.encode("I (mA) – é"), b"(I \\(mA\\) \x96 \xe9)".to_vec());
+        assert_eq!(font.encode("Δt\tx"), b"(?t x)".to_vec());
+    }
+
+    #[test]
+    fn wrapping() {
+        let font = CaptionFont::Helvetica;
+        // a is 5.56 points wide at size 10, i 2.22 and the space 2.78
+        assert_eq!(wrap("aaaa bbbb  cc", 40.0, 10.0, &font), vec!["aaaa", "bbbb cc"]);
+        assert_eq!(wrap("aaaaaaaaaaaa b", 40.0, 10.0, &font), vec!["aaaaaaaaaaaa", "b"]);
+        assert_eq!(wrap("iiiiii iiiiii WWW", 40.0, 10.0, &font), vec!["iiiiii iiiiii", "WWW"]);
+        assert!(wrap(" ", 50.0, 10.0, &font).is_empty());
+    }
+
+    #[test]
+    fn helvetica_widths() {
+        let font = CaptionFont::Helvetica;
+        assert!((font.width("Wi", 10.0) - 11.66).abs() < 1e-9);
+        assert!((font.width("é – Δ", 10.0) - (5.56 + 2.78 + 5.56 + 2.78 + 5.56)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unreadable_figures() {
+        let dir = std::env::temp_dir().join(format!("pdf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("broken.pdf"), "not a pdf").unwrap();
+        let mut pages = PdfPages::new();
+        pages.add_file(dir.join("missing.pdf"), "");
+        assert!(pages.write(dir.join("all.pdf")).unwrap_err().contains("missing.pdf"));
+        let mut pages = PdfPages::new();
+        pages.add_file(dir.join("broken.pdf"), "");
+        assert!(pages.write(dir.join("all.pdf")).unwrap_err().contains("broken.pdf"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
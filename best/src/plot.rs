@@ -1,7 +1,14 @@
+use std::str::FromStr;
+
 use plotly::color::{NamedColor, Rgb};
 use plotly::common::{Anchor, DashType, Font, Line, Marker, MarkerSymbol, Mode, Title};
 use plotly::layout::{Axis, Legend, Shape, ShapeLine, ShapeType, ItemSizing, Margin};
-use plotly::{ImageFormat, Layout, Plot, Scatter};
+use plotly::{Layout, Scatter};
+
+use crate::downsample::{self, Downsample};
+use crate::render::{save_html, save_image, save_json, Figure, Trace};
+pub use crate::render::shutdown_kaleido;
+use crate::series::IntoSeries;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LegendAl{
@@ -22,6 +29,37 @@ pub enum LineOrPoints{
     LineAndPoints,
 }
 
+// Names as in "top-right" or "line", used by plotdat and figure manifests
+impl FromStr for LegendAl {
+    type Err = String;
+    fn from_str(s: &str) -> Result<LegendAl, String> {
+        Ok(match s {
+            "top-right" => LegendAl::TopRight,
+            "top-left" => LegendAl::TopLeft,
+            "top-center" => LegendAl::TopCenter,
+            "bottom-right" => LegendAl::BottomRight,
+            "bottom-left" => LegendAl::BottomLeft,
+            "bottom-center" => LegendAl::BottomCenter,
+            "center-right" => LegendAl::CenterRight,
+            "center-left" => LegendAl::CenterLeft,
+            _ => return Err(format!("unknown legend position {:?}", s)),
+        })
+    }
+}
+
+impl FromStr for LineOrPoints {
+    type Err = String;
+    fn from_str(s: &str) -> Result<LineOrPoints, String> {
+        Ok(match s {
+            "line" => LineOrPoints::Line,
+            "points" => LineOrPoints::Points,
+            "both" => LineOrPoints::LineAndPoints,
+            _ => return Err(format!("unknown style {:?}", s)),
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct PlotPar{
     pub width: usize,
     pub height: usize,
@@ -45,6 +83,11 @@ pub struct PlotPar{
     pub line_scale: f64,
     pub font_family: String,
     pub show_grid: bool,
+    pub downsample: Downsample,
+    // files written by the plot functions: pdf, png, svg, jpeg, webp, eps, html or json
+    pub formats: Vec<String>,
+    // html with plotly.js and the fonts of font_family inside, for reading without network
+    pub offline_html: bool,
 }
 
 impl PlotPar{
@@ -52,16 +95,16 @@ impl PlotPar{
         PlotPar {
             width,
             height,
-            xlab: format!("{}", xlab),
-            ylab: format!("{}", ylab),
+            xlab: xlab.to_string(),
+            ylab: ylab.to_string(),
             log_x: false,
             log_y: false,
             custom_range_x: false,
             custom_range_y: false,
             range_x: [0.0; 2],
             range_y: [0.0; 2],
-            title: format!("{}", title),
-            flnm: format!("{}", flnm),
+            title: title.to_string(),
+            flnm: flnm.to_string(),
             show_legend: true,
             legends,
             legend_al: LegendAl::TopRight,
@@ -70,134 +113,110 @@ impl PlotPar{
             dashes: vec![DashType::Solid; 100],
             font_scale: 1.0,
             line_scale: 1.0,
-            font_family: format!("Serif"),
+            font_family: "Serif".to_string(),
             show_grid: true,
+            downsample: Downsample::Off,
+            formats: vec!["pdf".to_string(), "png".to_string()],
+            offline_html: false,
         }
     }
 }
 
-pub fn line_plot(x: &Vec<Vec<f64>>, y: &Vec<Vec<f64>>, plot_par: &PlotPar) {
-    let lines_number = x.len();
-    let bgcol = Rgb::new(255, 255, 255);
-    let forecol = Rgb::new(0, 0, 0);
-    let gridcol = Rgb::new(220, 220, 220);
-    let transp = NamedColor::Transparent;
-    let thick: usize = (3.0 * plot_par.line_scale) as usize;
-    let medium: usize = (5.0 * plot_par.line_scale) as usize;
-    let msize: usize = (10.0 * plot_par.line_scale) as usize;
-    let fsz_title: usize = (38.0*plot_par.font_scale) as usize;
-    let fsz_legend: usize = (36.0*plot_par.font_scale) as usize;
-    let fsz_ticks: usize = (32.0*plot_par.font_scale) as usize;
-    let fsz_axes: usize = (38.0*plot_par.font_scale) as usize;
-    let dashes: Vec<DashType> = plot_par.dashes.clone();
+pub fn line_plot<'a, S: IntoSeries<'a>>(series: S, plot_par: &PlotPar) {
+    let series = series.into_series();
+    let lines_number = series.len();
+    let style = Style::new(plot_par);
 
-    let mut traces = Vec::new();
+    let mut figure = Figure::new(make_layout(plot_par, &style));
 
     for l in 0..lines_number {
-        match plot_par.line_or_points[l] {
-            LineOrPoints::Line => {
-                traces.push(
-                    Scatter::new(x[l].clone(), y[l].clone())
-                        .name(&plot_par.legends[l])
-                        .mode(Mode::Lines)
-                        .line(Line::new()
-                            .color(Rgb::new(plot_par.colors[l][0], plot_par.colors[l][1], plot_par.colors[l][2]))
-                            .width(medium as f64).dash(dashes[l].clone())
-                        ),
-                )
-            },
-            LineOrPoints::Points => {
-                traces.push(
-                    Scatter::new(x[l].clone(), y[l].clone())
-                        .name(&plot_par.legends[l])
-                        .mode(Mode::Markers)
-                        .marker(Marker::new().size(msize)
-                        .color(Rgb::new(plot_par.colors[l][0], plot_par.colors[l][1], plot_par.colors[l][2]))
-                        .symbol(MarkerSymbol::Circle)
-                    ),
-                )
-            },
-            LineOrPoints::LineAndPoints => {
-                traces.push(
-                    Scatter::new(x[l].clone(), y[l].clone())
-                        .name(&plot_par.legends[l])
-                        .mode(Mode::LinesMarkers)
-                        .line(Line::new()
-                            .color(Rgb::new(plot_par.colors[l][0], plot_par.colors[l][1], plot_par.colors[l][2]))
-                            .width(medium as f64).dash(dashes[l].clone())
-                        )
-                        .marker(Marker::new().size(msize).symbol(MarkerSymbol::Circle)),
-                )
-            },
-        }
+        figure.add_trace(make_trace(series.x(l), series.y(l), l, plot_par, &style));
     }
 
-    let title = Title::new(&plot_par.title)
-        .font(Font::new().size(fsz_title).family(&plot_par.font_family).color(forecol));
-
-    let legend_bottom_right = Legend::new()
-        .x(0.99)
-        .x_anchor(Anchor::Right)
-        .y(0.01)
-        .y_anchor(Anchor::Bottom);
-
-    let legend_top_right = Legend::new()
-        .x(0.99)
-        .x_anchor(Anchor::Right)
-        .y(0.99)
-        .y_anchor(Anchor::Top);
+    write_plot(&figure, plot_par);
+}
 
-    let legend_bottom_left = Legend::new()
-        .x(0.01)
-        .x_anchor(Anchor::Left)
-        .y(0.01)
-        .y_anchor(Anchor::Bottom);
+// Colors, line widths and font sizes shared by all plot types
+pub(crate) struct Style {
+    pub bgcol: Rgb,
+    pub forecol: Rgb,
+    pub gridcol: Rgb,
+    pub thick: usize,
+    pub medium: usize,
+    pub msize: usize,
+    pub fsz_title: usize,
+    pub fsz_legend: usize,
+    pub fsz_ticks: usize,
+    pub fsz_axes: usize,
+}
 
-    let legend_top_left = Legend::new()
-        .x(0.01)
-        .x_anchor(Anchor::Left)
-        .y(0.99)
-        .y_anchor(Anchor::Top);
+impl Style {
+    pub fn new(plot_par: &PlotPar) -> Style {
+        Style {
+            bgcol: Rgb::new(255, 255, 255),
+            forecol: Rgb::new(0, 0, 0),
+            gridcol: Rgb::new(220, 220, 220),
+            thick: (3.0 * plot_par.line_scale) as usize,
+            medium: (5.0 * plot_par.line_scale) as usize,
+            msize: (10.0 * plot_par.line_scale) as usize,
+            fsz_title: (38.0*plot_par.font_scale) as usize,
+            fsz_legend: (36.0*plot_par.font_scale) as usize,
+            fsz_ticks: (32.0*plot_par.font_scale) as usize,
+            fsz_axes: (38.0*plot_par.font_scale) as usize,
+        }
+    }
+}
 
-    let legend_top_center = Legend::new()
-        .x(0.5)
-        .x_anchor(Anchor::Center)
-        .y(0.99)
-        .y_anchor(Anchor::Top);
+pub(crate) fn color(plot_par: &PlotPar, l: usize) -> Rgb {
+    Rgb::new(plot_par.colors[l][0], plot_par.colors[l][1], plot_par.colors[l][2])
+}
 
-    let legend_bottom_center = Legend::new()
-        .x(0.5)
-        .x_anchor(Anchor::Center)
-        .y(0.01)
-        .y_anchor(Anchor::Bottom);
+// Trace number l styled according to plot_par
+pub(crate) fn make_trace<'a>(x: &'a [f64], y: &'a [f64], l: usize, plot_par: &PlotPar, style: &Style) -> Trace<'a> {
+    let (x, y) = downsample::for_plot(x, y, plot_par);
+    let scatter = match plot_par.line_or_points[l] {
+        LineOrPoints::Line => {
+            Scatter::new(Vec::<f64>::new(), Vec::<f64>::new())
+                .name(&plot_par.legends[l])
+                .mode(Mode::Lines)
+                .line(Line::new()
+                    .color(color(plot_par, l))
+                    .width(style.medium as f64).dash(plot_par.dashes[l].clone())
+                )
+        },
+        LineOrPoints::Points => {
+            Scatter::new(Vec::<f64>::new(), Vec::<f64>::new())
+                .name(&plot_par.legends[l])
+                .mode(Mode::Markers)
+                .marker(Marker::new().size(style.msize)
+                .color(color(plot_par, l))
+                .symbol(MarkerSymbol::Circle)
+            )
+        },
+        LineOrPoints::LineAndPoints => {
+            Scatter::new(Vec::<f64>::new(), Vec::<f64>::new())
+                .name(&plot_par.legends[l])
+                .mode(Mode::LinesMarkers)
+                .line(Line::new()
+                    .color(color(plot_par, l))
+                    .width(style.medium as f64).dash(plot_par.dashes[l].clone())
+                )
+                .marker(Marker::new().size(style.msize).symbol(MarkerSymbol::Circle))
+        },
+    };
+    Trace::new(scatter, vec![("x", x), ("y", y)])
+}
 
-    let legend_center_right = Legend::new()
-        .x(1.01)
-        .x_anchor(Anchor::Left)
-        .y(0.5)
-        .y_anchor(Anchor::Center);
+pub(crate) fn make_layout(plot_par: &PlotPar, style: &Style) -> Layout {
+    let bgcol = style.bgcol;
+    let forecol = style.forecol;
+    let transp = NamedColor::Transparent;
+    let thick = style.thick;
 
-    let legend_center_left = Legend::new()
-        .x(0.01)
-        .x_anchor(Anchor::Left)
-        .y(0.5)
-        .y_anchor(Anchor::Center);
+    let title = Title::new(&plot_par.title)
+        .font(Font::new().size(style.fsz_title).family(&plot_par.font_family).color(forecol));
 
-    let legend = match plot_par.legend_al {
-        LegendAl::BottomLeft => legend_bottom_left,
-        LegendAl::BottomRight => legend_bottom_right,
-        LegendAl::TopLeft => legend_top_left,
-        LegendAl::TopRight => legend_top_right,
-        LegendAl::BottomCenter => legend_bottom_center,
-        LegendAl::CenterLeft => legend_center_left,
-        LegendAl::CenterRight => legend_center_right,
-        LegendAl::TopCenter => legend_top_center,
-    }.font(Font::new().size(fsz_legend).color(forecol).family(&plot_par.font_family))
-        .border_width(thick)
-        .border_color(forecol)
-        .background_color(bgcol)
-        .item_width(100)
-        .item_sizing(ItemSizing::Trace);
+    let legend = make_legend(plot_par, style);
 
     let axis = Axis::new()
         .position(0.0)
@@ -205,21 +224,21 @@ pub fn line_plot(x: &Vec<Vec<f64>>, y: &Vec<Vec<f64>>, plot_par: &PlotPar) {
         .line_color(forecol)
         .line_width(thick)
         .tick_length(9)
-        .tick_width(medium)
+        .tick_width(style.medium)
         .tick_color(forecol)
         .tick_font(Font::new().color(forecol))
         .zero_line(false)
         .show_grid(true).grid_width(thick)
-        .grid_color(gridcol).auto_margin(true);
+        .grid_color(style.gridcol).auto_margin(true);
 
     let mut axisx = axis.clone().title(
         Title::new(&plot_par.xlab)
-            .font(Font::new().size(fsz_axes).color(forecol).family(&plot_par.font_family)));
+            .font(Font::new().size(style.fsz_axes).color(forecol).family(&plot_par.font_family)));
 
     let mut axisy = axis
         .clone()
         .title(Title::new(&plot_par.ylab)
-            .font(Font::new().size(fsz_axes).color(forecol).family(&plot_par.font_family)));
+            .font(Font::new().size(style.fsz_axes).color(forecol).family(&plot_par.font_family)));
 
     if plot_par.log_x {
         axisx = axisx.exponent_format(plotly::common::ExponentFormat::SmallE).type_(plotly::layout::AxisType::Log)
@@ -262,7 +281,7 @@ pub fn line_plot(x: &Vec<Vec<f64>>, y: &Vec<Vec<f64>>, plot_par: &PlotPar) {
     let mut layout = Layout::new()
         .width(plot_par.width)
         .height(plot_par.height)
-        .font(Font::new().size(fsz_ticks))
+        .font(Font::new().size(style.fsz_ticks))
         .title(title)
         .legend(legend)
         .show_legend(plot_par.show_legend)
@@ -281,20 +300,87 @@ pub fn line_plot(x: &Vec<Vec<f64>>, y: &Vec<Vec<f64>>, plot_par: &PlotPar) {
     // Here's how to fix legend
     //layout.add_shape(Shape::new().shape_type(ShapeType::Rect));
 
-    let mut plot = Plot::new();
+    layout
+}
 
-    for l in 0..lines_number {
-        let trace = traces[l].clone();
-        plot.add_trace(trace);
-    }
-    plot.set_layout(layout);
+pub(crate) fn make_legend(plot_par: &PlotPar, style: &Style) -> Legend {
+    let forecol = style.forecol;
+
+    let legend_bottom_right = Legend::new()
+        .x(0.99)
+        .x_anchor(Anchor::Right)
+        .y(0.01)
+        .y_anchor(Anchor::Bottom);
+
+    let legend_top_right = Legend::new()
+        .x(0.99)
+        .x_anchor(Anchor::Right)
+        .y(0.99)
+        .y_anchor(Anchor::Top);
+
+    let legend_bottom_left = Legend::new()
+        .x(0.01)
+        .x_anchor(Anchor::Left)
+        .y(0.01)
+        .y_anchor(Anchor::Bottom);
 
-    //let config = plotly::Configuration::new().static_plot(true);
-    //plot.set_configuration(config);
+    let legend_top_left = Legend::new()
+        .x(0.01)
+        .x_anchor(Anchor::Left)
+        .y(0.99)
+        .y_anchor(Anchor::Top);
 
-    plot.write_image(&plot_par.flnm, ImageFormat::PDF, plot_par.width, plot_par.height, 1.0);
-    plot.write_image(&plot_par.flnm, ImageFormat::PNG, plot_par.width, plot_par.height, 1.0);
-    //plot.write_html(&format!("{}.html", plot_par.flnm));
+    let legend_top_center = Legend::new()
+        .x(0.5)
+        .x_anchor(Anchor::Center)
+        .y(0.99)
+        .y_anchor(Anchor::Top);
+
+    let legend_bottom_center = Legend::new()
+        .x(0.5)
+        .x_anchor(Anchor::Center)
+        .y(0.01)
+        .y_anchor(Anchor::Bottom);
+
+    let legend_center_right = Legend::new()
+        .x(1.01)
+        .x_anchor(Anchor::Left)
+        .y(0.5)
+        .y_anchor(Anchor::Center);
+
+    let legend_center_left = Legend::new()
+        .x(0.01)
+        .x_anchor(Anchor::Left)
+        .y(0.5)
+        .y_anchor(Anchor::Center);
+
+    match plot_par.legend_al {
+        LegendAl::BottomLeft => legend_bottom_left,
+        LegendAl::BottomRight => legend_bottom_right,
+        LegendAl::TopLeft => legend_top_left,
+        LegendAl::TopRight => legend_top_right,
+        LegendAl::BottomCenter => legend_bottom_center,
+        LegendAl::CenterLeft => legend_center_left,
+        LegendAl::CenterRight => legend_center_right,
+        LegendAl::TopCenter => legend_top_center,
+    }.font(Font::new().size(style.fsz_legend).color(forecol).family(&plot_par.font_family))
+        .border_width(style.thick)
+        .border_color(forecol)
+        .background_color(style.bgcol)
+        .item_width(100)
+        .item_sizing(ItemSizing::Trace)
+}
+
+pub(crate) fn write_plot(figure: &Figure, plot_par: &PlotPar) {
+    for format in plot_par.formats.iter() {
+        if format == "html" {
+            save_html(figure, &plot_par.flnm, plot_par.width, plot_par.height, plot_par.offline_html, &plot_par.font_family);
+        } else if format == "json" {
+            save_json(figure, &plot_par.flnm);
+        } else {
+            save_image(figure, &plot_par.flnm, format, plot_par.width, plot_par.height, 1.0);
+        }
+    }
 }
 
 pub const COLORS: [[u8; 3]; 48] = [
@@ -0,0 +1,87 @@
+// Rendering many figures at once: jobs, usually a call of line_plot or another plot function,
+// run on a few threads. Kaleido processes are shared by all of them: a render takes an idle
+// one from render.rs's pool, or starts one, and gives it back for the next render.
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::watch::panic_message;
+
+type Job<'a> = Box<dyn FnOnce() -> Result<(), String> + Send + 'a>;
+
+pub struct RenderQueue<'a> {
+    // jobs run at the same time [default: the number of CPUs]
+    pub workers: usize,
+    names: Vec<String>,
+    jobs: Vec<Job<'a>>,
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> RenderQueue<'a> {
+        RenderQueue {
+            workers: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            names: Vec::new(),
+            jobs: Vec::new(),
+        }
+    }
+
+    // A job fails by returning Err, or by panicking as the plot functions do
+    pub fn add<F: FnOnce() -> Result<(), String> + Send + 'a>(&mut self, name: &str, job: F) {
+        self.names.push(name.to_string());
+        self.jobs.push(Box::new(job));
+    }
+
+    // Runs every job and returns the names with the results, in the order the jobs were added.
+    // A panic's message is its job's Err; the panic hook still reports it as usual.
+    pub fn run(self) -> Vec<(String, Result<(), String>)> {
+        let n = self.jobs.len();
+        let jobs = Mutex::new(self.jobs.into_iter().enumerate());
+        let results = Mutex::new(vec![None; n]);
+
+        thread::scope(|scope| {
+            for _ in 0..self.workers.clamp(1, n.max(1)) {
+                scope.spawn(|| loop {
+                    let next = jobs.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let Some((k, job)) = next else { break };
+                    let result = panic::catch_unwind(AssertUnwindSafe(job))
+                        .unwrap_or_else(|payload| Err(panic_message(payload.as_ref())));
+                    results.lock().unwrap_or_else(|e| e.into_inner())[k] = Some(result);
+                });
+            }
+        });
+
+        let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        self.names.into_iter()
+            .zip(results)
+            .map(|(name, result)| (name, result.unwrap_or_else(|| Err("not run".to_string()))))
+            .collect()
+    }
+}
+
+impl Default for RenderQueue<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_in_order() {
+        let mut queue = RenderQueue::new();
+        queue.workers = 2;
+        queue.add("ok", || Ok(()));
+        queue.add("err", || Err("no data".to_string()));
+        queue.add("panic", || panic!("Can't write {:?}", "fig.pdf"));
+        queue.add("last", || Ok(()));
+        let results = queue.run();
+        assert_eq!(results, vec![
+            ("ok".to_string(), Ok(())),
+            ("err".to_string(), Err("no data".to_string())),
+            ("panic".to_string(), Err("Can't write \"fig.pdf\"".to_string())),
+            ("last".to_string(), Ok(())),
+        ]);
+    }
+}
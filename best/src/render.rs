@@ -0,0 +1,381 @@
+// Rendering figures with kaleido. Trace data is serialized straight from the
+// caller's slices into kaleido's stdin, without copying it into plotly traces.
+// Kaleido processes are kept running and reused, by later plots and by other threads.
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+use directories::ProjectDirs;
+use plotly::Layout;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{Map, Value};
+
+use crate::fonts::font_faces;
+
+// Trace style from a plotly trace plus the data arrays, e.g. ("x", ...) and ("y", ...)
+pub(crate) struct Trace<'a> {
+    style: Map<String, Value>,
+    data: Vec<(&'static str, Cow<'a, [f64]>)>,
+}
+
+impl<'a> Trace<'a> {
+    // style is any plotly trace, built with empty data
+    pub fn new<T: Serialize>(style: T, data: Vec<(&'static str, Cow<'a, [f64]>)>) -> Trace<'a> {
+        let mut style = match serde_json::to_value(style) {
+            Ok(Value::Object(map)) => map,
+            _ => panic!("Can't convert trace style to JSON"),
+        };
+        for (key, _) in data.iter() {
+            style.remove(*key);
+        }
+        Trace { style, data }
+    }
+}
+
+impl Serialize for Trace<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.style.len() + self.data.len()))?;
+        for (key, value) in self.style.iter() {
+            map.serialize_entry(key, value)?;
+        }
+        // NaN and infinity are written as null, which breaks the line in plotly
+        for (key, values) in self.data.iter() {
+            map.serialize_entry(key, values.as_ref())?;
+        }
+        map.end()
+    }
+}
+
+pub(crate) struct Figure<'a> {
+    pub traces: Vec<Trace<'a>>,
+    pub layout: Value,
+}
+
+impl<'a> Figure<'a> {
+    pub fn new(layout: Layout) -> Figure<'a> {
+        Figure {
+            traces: Vec::new(),
+            layout: serde_json::to_value(layout).expect("Can't convert layout to JSON"),
+        }
+    }
+
+    pub fn add_trace(&mut self, trace: Trace<'a>) {
+        self.traces.push(trace)
+    }
+}
+
+impl Serialize for Figure<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("data", &self.traces)?;
+        map.serialize_entry("layout", &self.layout)?;
+        map.end()
+    }
+}
+
+// Kaleido installed by the kaleido feature of plotly
+pub(crate) fn kaleido_path() -> PathBuf {
+    let dirs = ProjectDirs::from("org", "plotly", "kaleido")
+        .expect("Could not find plotly_kaleido config directory");
+    #[cfg(target_os = "windows")]
+    let path = dirs.config_dir().join("kaleido.cmd");
+    #[cfg(not(target_os = "windows"))]
+    let path = dirs.config_dir().join("kaleido");
+    path
+}
+
+// Kaleido processes that are started once and then render one request after another.
+// Idle ones wait in IDLE until shutdown_kaleido or the end of the program.
+pub(crate) struct Kaleido {
+    process: Child,
+    stdin: BufWriter<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+// Idle processes, taken by one plot at a time
+pub(crate) struct Pool {
+    idle: Mutex<Vec<Kaleido>>,
+}
+
+static IDLE: Pool = Pool::new();
+
+impl Pool {
+    pub const fn new() -> Pool {
+        Pool { idle: Mutex::new(Vec::new()) }
+    }
+
+    // An idle process, or a new one from spawn when all are busy
+    pub fn take<F: FnOnce() -> Kaleido>(&self, spawn: F) -> Kaleido {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        idle.unwrap_or_else(spawn)
+    }
+
+    pub fn give_back(&self, kaleido: Kaleido) {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner()).push(kaleido);
+    }
+
+    // Ends the idle processes and returns how many there were; busy ones are given back later
+    pub fn shutdown(&self) -> usize {
+        let idle = std::mem::take(&mut *self.idle.lock().unwrap_or_else(|e| e.into_inner()));
+        idle.len()
+    }
+}
+
+// Ends the kaleido processes kept for later plots. Statics are never dropped, so programs
+// call it before they exit, also through std::process::exit; later plots start new ones.
+pub fn shutdown_kaleido() {
+    IDLE.shutdown();
+}
+
+impl Kaleido {
+    pub fn spawn() -> Kaleido {
+        let kaleido = kaleido_path();
+        let mut command = Command::new(&kaleido);
+        command.current_dir(kaleido.parent().unwrap())
+            .args([
+                "plotly",
+                "--disable-gpu",
+                "--allow-file-access-from-files",
+                "--disable-breakpad",
+                "--disable-dev-shm-usage",
+                "--single-process",
+            ]);
+        Kaleido::start(&mut command).expect("failed to spawn Kaleido binary")
+    }
+
+    // Any program that speaks kaleido's stdin protocol
+    pub fn start(command: &mut Command) -> io::Result<Kaleido> {
+        let mut process = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = BufWriter::new(process.stdin.take().unwrap());
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        Ok(Kaleido { process, stdin, stdout })
+    }
+
+    // An idle process, or a new one when all are busy
+    pub fn take() -> Kaleido {
+        IDLE.take(Kaleido::spawn)
+    }
+
+    pub fn give_back(self) {
+        IDLE.give_back(self);
+    }
+
+    // Image bytes; Err(None) when the process failed and can't be used again,
+    // Err(Some(message)) when kaleido couldn't render this figure
+    pub fn render(&mut self, figure: &Figure, format: &str, width: usize, height: usize, scale: f64) -> Result<Vec<u8>, Option<String>> {
+        write_request(&mut self.stdin, figure, format, width, height, scale).map_err(|_| None)?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line).map_err(|_| None)? == 0 {
+                return Err(None);
+            }
+            if let Some(result) = read_response(&line, format) {
+                return result.map_err(Some);
+            }
+        }
+    }
+}
+
+impl Drop for Kaleido {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// Writes flnm.<format> (pdf, png, svg, jpeg, webp or eps)
+pub(crate) fn save_image(figure: &Figure, flnm: &str, format: &str, width: usize, height: usize, scale: f64) {
+    let mut kaleido = Kaleido::take();
+    match kaleido.render(figure, format, width, height, scale) {
+        Ok(image) => {
+            kaleido.give_back();
+            write_image_file(&image, flnm, format);
+        },
+        Err(Some(message)) => {
+            kaleido.give_back();
+            panic!("failed to export plot to {}.{}: {}", flnm, format, message);
+        },
+        Err(None) => panic!("failed to export plot to {}.{}", flnm, format),
+    }
+}
+
+// Writes flnm.html, an interactive page that loads plotly.js from its CDN. Offline pages have
+// plotly.js and the fonts of font_family in the file instead, and render without network.
+pub(crate) fn save_html(figure: &Figure, flnm: &str, width: usize, height: usize, offline: bool, font_family: &str) {
+    let path = PathBuf::from(flnm).with_extension("html");
+    let write = || -> std::io::Result<()> {
+        let mut w = BufWriter::new(File::create(&path)?);
+        writeln!(w, "<!doctype html>")?;
+        writeln!(w, "<html lang=\"en\">")?;
+        writeln!(w, "<head>\n    <meta charset=\"utf-8\" />")?;
+        if offline {
+            writeln!(w, "    <style>\n{}    </style>", font_faces(font_family))?;
+            writeln!(w, "    <script type=\"text/javascript\">{}</script>\n</head>", plotly_js())?;
+        } else {
+            writeln!(w, "    <script src=\"https://cdn.plot.ly/plotly-2.12.1.min.js\"></script>\n</head>")?;
+        }
+        writeln!(w, "<body>")?;
+        writeln!(w, "    <div id=\"plot\" style=\"width:{}px; height:{}px;\"></div>", width, height)?;
+        // text is measured when the plot is drawn, so the embedded fonts must be loaded by then
+        write!(w, "    <script>\n        document.fonts.ready.then(() => Plotly.newPlot(\"plot\", ")?;
+        serde_json::to_writer(&mut w, figure)?;
+        writeln!(w, "));\n    </script>\n</body>\n</html>")?;
+        w.flush()
+    };
+    write().unwrap_or_else(|_| panic!("Can't write plot to {:?}", path));
+}
+
+// Writes flnm.json, the figure as plotly.js takes it, for Report and other pages
+pub(crate) fn save_json(figure: &Figure, flnm: &str) {
+    let path = PathBuf::from(flnm).with_extension("json");
+    let write = || -> std::io::Result<()> {
+        let mut w = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer(&mut w, figure)?;
+        w.flush()
+    };
+    write().unwrap_or_else(|_| panic!("Can't write plot to {:?}", path));
+}
+
+// The plotly.js bundle of the plotly crate, taken from its html with local plotly.js
+pub(crate) fn plotly_js() -> &'static str {
+    static PLOTLY_JS: OnceLock<String> = OnceLock::new();
+    PLOTLY_JS.get_or_init(|| {
+        let mut plot = plotly::Plot::new();
+        plot.use_local_plotly();
+        let html = plot.to_html();
+        let start = "<script type=\"text/javascript\">";
+        let js = html.find(start)
+            .map(|k| &html[k + start.len()..])
+            .and_then(|js| js.find("<div id=\"plotly-html-element\"").and_then(|end| js[..end].rfind("</script>")).map(|end| &js[..end]))
+            .expect("Can't find plotly.js in the html of the plotly crate");
+        js.to_string()
+    })
+}
+
+// One request line of kaleido's stdin protocol
+pub(crate) fn write_request<W: Write>(w: &mut W, figure: &Figure, format: &str, width: usize, height: usize, scale: f64) -> std::io::Result<()> {
+    struct Request<'a, 'b> {
+        format: &'b str,
+        width: usize,
+        height: usize,
+        scale: f64,
+        data: &'b Figure<'a>,
+    }
+    impl Serialize for Request<'_, '_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(5))?;
+            map.serialize_entry("format", self.format)?;
+            map.serialize_entry("width", &self.width)?;
+            map.serialize_entry("height", &self.height)?;
+            map.serialize_entry("scale", &self.scale)?;
+            map.serialize_entry("data", self.data)?;
+            map.end()
+        }
+    }
+    serde_json::to_writer(&mut *w, &Request { format, width, height, scale, data: figure })?;
+    writeln!(w)?;
+    w.flush()
+}
+
+// Image bytes or kaleido's error message from an output line, None for status lines without an image
+pub(crate) fn read_response(line: &str, format: &str) -> Option<Result<Vec<u8>, String>> {
+    let response: Value = serde_json::from_str(line).ok()?;
+    if response["code"].as_i64().unwrap_or(0) != 0 {
+        return Some(Err(response["message"].as_str().map(String::from).unwrap_or_else(|| response["message"].to_string())));
+    }
+    let result = response["result"].as_str()?;
+    Some(match format {
+        "svg" | "eps" => Ok(result.as_bytes().to_vec()),
+        _ => base64::decode(result).map_err(|_| "Kaleido returned invalid data".to_string()),
+    })
+}
+
+pub(crate) fn write_image_file(image: &[u8], flnm: &str, format: &str) {
+    let path = PathBuf::from(flnm).with_extension(format);
+    let mut file = File::create(&path).expect("Error creating file");
+    file.write_all(image).unwrap_or_else(|_| panic!("Can't write image to {:?}", path));
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // Answers every request with "hello" after a status line, or with an error for figures titled fail
+    fn mock() -> Kaleido {
+        let script = r#"while read -r line; do
+            case "$line" in
+                *'"text":"fail"'*) echo '{"code": 1, "message": "bad figure"}';;
+                *) echo 'starting'; echo '{"code": 0, "result": "aGVsbG8="}';;
+            esac
+        done"#;
+        Kaleido::start(Command::new("sh").args(["-c", script])).unwrap()
+    }
+
+    fn figure(title: &str) -> Figure<'static> {
+        let mut figure = Figure::new(Layout::new().title(plotly::common::Title::new(title)));
+        figure.add_trace(Trace::new(plotly::Scatter::new(Vec::<f64>::new(), Vec::<f64>::new()), vec![("x", Cow::Owned(vec![1.0, f64::NAN]))]));
+        figure
+    }
+
+    #[test]
+    fn requests_and_responses() {
+        let mut request = Vec::new();
+        write_request(&mut request, &figure("a"), "png", 800, 600, 2.0).unwrap();
+        assert!(request.ends_with(b"\n"));
+        let request: Value = serde_json::from_slice(&request).unwrap();
+        assert_eq!((request["format"].as_str(), request["width"].as_u64(), request["scale"].as_f64()), (Some("png"), Some(800), Some(2.0)));
+        assert_eq!(request["data"]["data"][0]["x"], serde_json::json!([1.0, null]));
+
+        assert_eq!(read_response("starting", "png"), None);
+        assert_eq!(read_response(r#"{"code": 0, "result": "aGVsbG8="}"#, "png"), Some(Ok(b"hello".to_vec())));
+        assert_eq!(read_response(r#"{"code": 0, "result": "<svg/>"}"#, "svg"), Some(Ok(b"<svg/>".to_vec())));
+        assert_eq!(read_response(r#"{"code": 0, "result": "not base64!"}"#, "png"), Some(Err("Kaleido returned invalid data".to_string())));
+        assert_eq!(read_response(r#"{"code": 525, "message": "bad figure"}"#, "png"), Some(Err("bad figure".to_string())));
+    }
+
+    #[test]
+    fn render() {
+        let mut kaleido = mock();
+        assert_eq!(kaleido.render(&figure("a"), "png", 800, 600, 1.0), Ok(b"hello".to_vec()));
+        assert_eq!(kaleido.render(&figure("fail"), "png", 800, 600, 1.0), Err(Some("bad figure".to_string())));
+        // the process renders on after an error
+        assert_eq!(kaleido.render(&figure("a"), "svg", 800, 600, 1.0), Ok(b"aGVsbG8=".to_vec()));
+
+        let mut ended = Kaleido::start(Command::new("sh").args(["-c", "exit 0"])).unwrap();
+        assert_eq!(ended.render(&figure("a"), "png", 800, 600, 1.0), Err(None));
+    }
+
+    #[test]
+    fn pool() {
+        let pool = Pool::new();
+        let spawned = Cell::new(0);
+        let spawn = || {
+            spawned.set(spawned.get() + 1);
+            mock()
+        };
+        let first = pool.take(spawn);
+        let second = pool.take(spawn);
+        assert_eq!(spawned.get(), 2);
+        let id = first.process.id();
+        pool.give_back(first);
+        // an idle process is reused
+        let mut again = pool.take(spawn);
+        assert_eq!((spawned.get(), again.process.id()), (2, id));
+        assert_eq!(again.render(&figure("a"), "png", 800, 600, 1.0), Ok(b"hello".to_vec()));
+        pool.give_back(again);
+        pool.give_back(second);
+        assert_eq!(pool.shutdown(), 2);
+        assert_eq!(pool.shutdown(), 0);
+        drop(pool.take(spawn));
+        assert_eq!(spawned.get(), 3);
+    }
+}
@@ -0,0 +1,257 @@
+// Reports of many figures: one html page with a table of contents and the figures inline,
+// or Markdown next to the images. A figure is added with the PlotPar it was plotted with and
+// read when the report is written: flnm.json, written with the json format, is shown as an
+// interactive plot, otherwise flnm.svg, .png, .jpeg or .webp as an image.
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::fonts::font_faces;
+use crate::plot::PlotPar;
+use crate::render::plotly_js;
+
+// Formats of the images a report shows when there is no json, in the order they're looked for
+pub const IMAGE_FORMATS: [&str; 4] = ["svg", "png", "jpeg", "webp"];
+const MIME_TYPES: [&str; 4] = ["image/svg+xml", "image/png", "image/jpeg", "image/webp"];
+
+// Tags of plotly's text formatting, which are html as well
+const TEXT_TAGS: [&str; 8] = ["sup", "sub", "b", "i", "em", "s", "u", "br"];
+
+pub struct Report {
+    pub title: String,
+    figures: Vec<ReportFigure>,
+}
+
+struct ReportFigure {
+    plot_par: PlotPar,
+    caption: String,
+    data_files: Vec<String>,
+}
+
+impl Report {
+    pub fn new(title: &str) -> Report {
+        Report {
+            title: title.to_string(),
+            figures: Vec::new(),
+        }
+    }
+
+    // A figure written by line_plot or another plot function with plot_par, and the data files it shows
+    pub fn add(&mut self, plot_par: &PlotPar, caption: &str, data_files: &[&str]) {
+        self.figures.push(ReportFigure {
+            plot_par: plot_par.clone(),
+            caption: caption.to_string(),
+            data_files: data_files.iter().map(|f| f.to_string()).collect(),
+        });
+    }
+
+    // A single file: plotly.js, the fonts and the images are inside, so it works without network.
+    // An error when a figure has neither json nor an image or the file can't be written.
+    pub fn write_html<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut html = String::new();
+        let mut plots = String::new();
+        for (k, figure) in self.figures.iter().enumerate() {
+            let id = format!("figure-{}", k + 1);
+            html.push_str(&format!("<section id=\"{}\">\n<h2>{}. {}</h2>\n<figure>\n", id, k + 1, text_html(&figure.title())));
+            let json = PathBuf::from(&figure.plot_par.flnm).with_extension("json");
+            if let Ok(json) = fs::read_to_string(&json) {
+                html.push_str(&format!("<div class=\"plot\"><div id=\"plot-{}\"></div></div>\n", k + 1));
+                // "</" in a string of the json would end the script
+                plots.push_str(&format!("    Plotly.newPlot(\"plot-{}\", {});\n", k + 1, json.replace("</", "<\\/")));
+            } else {
+                let (image, mime) = figure.image()?;
+                let data = fs::read(&image).map_err(|e| format!("can't read {}: {}", image.display(), e))?;
+                html.push_str(&format!("<img src=\"data:{};base64,{}\" alt=\"{}\" />\n", mime, base64::encode(data), escape(&plain_text(&figure.title()))));
+            }
+            if !figure.caption.is_empty() {
+                html.push_str(&format!("<figcaption>{}</figcaption>\n", escape(&figure.caption)));
+            }
+            html.push_str("</figure>\n");
+            if !figure.data_files.is_empty() {
+                let files: Vec<String> = figure.data_files.iter().map(|f| format!("<code>{}</code>", escape(f))).collect();
+                html.push_str(&format!("<p>Data: {}</p>\n", files.join(", ")));
+            }
+            html.push_str("<details>\n<summary>Plot settings</summary>\n<table>\n");
+            for (setting, value) in settings(&figure.plot_par) {
+                html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", setting, escape(&value)));
+            }
+            html.push_str("</table>\n</details>\n</section>\n");
+        }
+
+        let mut families: Vec<&str> = self.figures.iter().map(|f| f.plot_par.font_family.as_str()).collect();
+        families.sort();
+        families.dedup();
+        let write = || -> std::io::Result<()> {
+            let mut w = BufWriter::new(File::create(path)?);
+            writeln!(w, "<!doctype html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\" />")?;
+            writeln!(w, "<title>{}</title>", escape(&self.title))?;
+            writeln!(w, "<style>")?;
+            writeln!(w, "body {{ font-family: sans-serif; max-width: 1700px; margin: 2em auto; padding: 0 1em; }}")?;
+            writeln!(w, ".plot, figure {{ overflow-x: auto; margin: 0; }}")?;
+            writeln!(w, "img {{ max-width: 100%; }}")?;
+            writeln!(w, "figcaption {{ margin: 0.5em 0; font-style: italic; }}")?;
+            writeln!(w, "td {{ padding: 0.1em 1em 0.1em 0; vertical-align: top; }}")?;
+            write!(w, "{}", font_faces(&families.join(", ")))?;
+            writeln!(w, "</style>")?;
+            if !plots.is_empty() {
+                writeln!(w, "<script type=\"text/javascript\">{}</script>", plotly_js())?;
+            }
+            writeln!(w, "</head>\n<body>\n<h1>{}</h1>", escape(&self.title))?;
+            writeln!(w, "<nav>\n<h2>Contents</h2>\n<ol>")?;
+            for (k, figure) in self.figures.iter().enumerate() {
+                writeln!(w, "<li><a href=\"#figure-{}\">{}</a></li>", k + 1, text_html(&figure.title()))?;
+            }
+            writeln!(w, "</ol>\n</nav>")?;
+            write!(w, "{}", html)?;
+            if !plots.is_empty() {
+                writeln!(w, "<script>\ndocument.fonts.ready.then(() => {{\n{}}});\n</script>", plots)?;
+            }
+            writeln!(w, "</body>\n</html>")?;
+            w.flush()
+        };
+        write().map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+
+    // Markdown that shows the images of the figures; image paths are relative to the
+    // report when the images are in its directory or below. An error for a figure without an image.
+    pub fn write_markdown<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut md = format!("# {}\n\n", self.title);
+        for (k, figure) in self.figures.iter().enumerate() {
+            md.push_str(&format!("{}. [{}](#figure-{})\n", k + 1, figure.title(), k + 1));
+        }
+        for (k, figure) in self.figures.iter().enumerate() {
+            let (image, _) = figure.image()?;
+            let image = image.strip_prefix(dir).unwrap_or(&image);
+            md.push_str(&format!("\n<a id=\"figure-{}\"></a>\n\n## {}. {}\n\n", k + 1, k + 1, figure.title()));
+            md.push_str(&format!("![{}]({})\n\n", plain_text(&figure.title()), image.to_string_lossy().replace(' ', "%20")));
+            if !figure.caption.is_empty() {
+                md.push_str(&format!("*{}*\n\n", figure.caption));
+            }
+            if !figure.data_files.is_empty() {
+                let files: Vec<String> = figure.data_files.iter().map(|f| format!("`{}`", f)).collect();
+                md.push_str(&format!("Data: {}\n\n", files.join(", ")));
+            }
+            md.push_str("| Setting | Value |\n| --- | --- |\n");
+            for (setting, value) in settings(&figure.plot_par) {
+                md.push_str(&format!("| {} | {} |\n", setting, value.replace('|', "\\|")));
+            }
+        }
+        fs::write(path, md).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+}
+
+impl ReportFigure {
+    // the plot's title, or its file name without one
+    fn title(&self) -> String {
+        if !self.plot_par.title.is_empty() {
+            return self.plot_par.title.clone();
+        }
+        Path::new(&self.plot_par.flnm).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    fn image(&self) -> Result<(PathBuf, &'static str), String> {
+        IMAGE_FORMATS.iter().zip(MIME_TYPES)
+            .map(|(format, mime)| (PathBuf::from(&self.plot_par.flnm).with_extension(format), mime))
+            .find(|(image, _)| image.exists())
+            .ok_or_else(|| format!("no svg, png, jpeg or webp of {} for the report", self.plot_par.flnm))
+    }
+}
+
+fn settings(plot_par: &PlotPar) -> Vec<(&'static str, String)> {
+    let axis = |log: bool, custom: bool, range: [f64; 2]| {
+        let scale = if log { "log" } else { "linear" };
+        if custom { format!("{}, {} to {}", scale, range[0], range[1]) } else { scale.to_string() }
+    };
+    let mut settings = vec![
+        ("File", plot_par.flnm.clone()),
+        ("Size", format!("{} x {}", plot_par.width, plot_par.height)),
+        ("x label", plot_par.xlab.clone()),
+        ("y label", plot_par.ylab.clone()),
+        ("x axis", axis(plot_par.log_x, plot_par.custom_range_x, plot_par.range_x)),
+        ("y axis", axis(plot_par.log_y, plot_par.custom_range_y, plot_par.range_y)),
+    ];
+    if !plot_par.show_legend {
+        settings.push(("Legend", "hidden".to_string()));
+    } else if !plot_par.legends.is_empty() {
+        settings.push(("Legend", format!("{}, {:?}", plot_par.legends.join("; "), plot_par.legend_al)));
+    }
+    settings.extend([
+        ("Font", format!("{}, scale {}", plot_par.font_family, plot_par.font_scale)),
+        ("Line scale", plot_par.line_scale.to_string()),
+        ("Grid", if plot_par.show_grid { "shown" } else { "hidden" }.to_string()),
+        ("Downsampling", format!("{:?}", plot_par.downsample)),
+        ("Formats", plot_par.formats.join(", ")),
+    ]);
+    settings
+}
+
+// Plotly text with its formatting tags, e.g. "j, A/cm<sup>2</sup>", kept and everything else escaped
+fn text_html(text: &str) -> String {
+    text_pieces(text).into_iter()
+        .map(|(tag, piece)| match tag {
+            Some(tag) => format!("<{}>", tag),
+            None => escape(piece),
+        })
+        .collect()
+}
+
+// Plotly text without its formatting tags, <br> as a space
+fn plain_text(text: &str) -> String {
+    text_pieces(text).into_iter()
+        .map(|(tag, piece)| match tag.as_deref() {
+            Some("br") => " ",
+            Some(_) => "",
+            None => piece,
+        })
+        .collect()
+}
+
+// The formatting tags of plotly text, as "sup", "/sup" or "br", and the text between them
+fn text_pieces(text: &str) -> Vec<(Option<String>, &str)> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut search = 0;
+    while let Some(open) = text[search..].find('<').map(|k| search + k) {
+        search = open + 1;
+        let Some(close) = text[open..].find('>').map(|k| open + k) else { break };
+        let inner = text[open + 1..close].trim().trim_end_matches('/').trim().to_lowercase();
+        let name = inner.strip_prefix('/').unwrap_or(&inner);
+        if TEXT_TAGS.contains(&name) {
+            pieces.push((None, &text[start..open]));
+            pieces.push((Some(if name == "br" { name.to_string() } else { inner.clone() }), &text[open..=close]));
+            start = close + 1;
+            search = start;
+        }
+    }
+    pieces.push((None, &text[start..]));
+    pieces
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plotly_tags() {
+        assert_eq!(text_html("j, A/cm<sup>2</sup> & E<SUB>F</SUB>"), "j, A/cm<sup>2</sup> &amp; E<sub>F</sub>");
+        assert_eq!(text_html("a <br /> b < c <script>"), "a <br> b &lt; c &lt;script&gt;");
+        assert_eq!(plain_text("<b>I</b>-V<br>curve, x<sup>2</sup> < 1"), "I-V curve, x2 < 1");
+    }
+
+    #[test]
+    fn missing_image() {
+        let dir = std::env::temp_dir().join(format!("report-{}", std::process::id()));
+        let flnm = dir.join("missing").to_string_lossy().to_string();
+        let mut report = Report::new("Figures");
+        report.add(&PlotPar::new(100, 100, "x", "y", "", &flnm, Vec::new()), "", &[]);
+        assert!(report.write_markdown(dir.join("report.md")).unwrap_err().contains("missing"));
+        assert!(report.write_html(dir.join("report.html")).is_err());
+    }
+}
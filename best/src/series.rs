@@ -0,0 +1,297 @@
+// x and y data of the traces of one plot, borrowed from the caller.
+// Anything that is AsRef<[f64]> works, e.g. Vec<f64>, &[f64] or Arc<[f64]>.
+use std::borrow::Cow;
+
+#[derive(Clone)]
+pub struct Series<'a> {
+    x: Vec<Cow<'a, [f64]>>,
+    y: Vec<Cow<'a, [f64]>>,
+    shared_x: bool,
+}
+
+impl<'a> Series<'a> {
+    // One x per y, an error when their numbers differ
+    pub fn new<X: AsRef<[f64]>, Y: AsRef<[f64]>>(x: &'a [X], y: &'a [Y]) -> Result<Series<'a>, String> {
+        check_count(x.len(), y.len())?;
+        Ok(Series::pairs(x.iter().map(|x| x.as_ref()).collect(), y.iter().map(|y| y.as_ref()).collect()))
+    }
+
+    // Every y plotted against the same x, which is stored once,
+    // e.g. Series::shared_x(&x, &ys) or Series::shared_x(x, ys) with owned columns
+    pub fn shared_x<X: IntoColumn<'a>, Y: IntoColumn<'a>>(x: X, y: impl IntoIterator<Item = Y>) -> Series<'a> {
+        Series {
+            x: vec![x.into_column()],
+            y: y.into_iter().map(|y| y.into_column()).collect(),
+            shared_x: true,
+        }
+    }
+
+    // Columns converted with IntoColumn, e.g. from f32 or integer data: one x per y or
+    // a single x shared by all of them, an error for other numbers of x
+    pub fn from_columns<X: IntoColumn<'a>, Y: IntoColumn<'a>>(x: Vec<X>, y: Vec<Y>) -> Result<Series<'a>, String> {
+        if x.len() == 1 && y.len() != 1 {
+            let x = x.into_iter().next().unwrap();
+            return Ok(Series::shared_x(x, y));
+        }
+        check_count(x.len(), y.len())?;
+        Ok(Series::pairs(x, y))
+    }
+
+    // one x per y, which the caller has checked
+    fn pairs<X: IntoColumn<'a>, Y: IntoColumn<'a>>(x: Vec<X>, y: Vec<Y>) -> Series<'a> {
+        Series {
+            x: x.into_iter().map(|x| x.into_column()).collect(),
+            y: y.into_iter().map(|y| y.into_column()).collect(),
+            shared_x: false,
+        }
+    }
+
+    pub fn empty() -> Series<'a> {
+        Series { x: Vec::new(), y: Vec::new(), shared_x: false }
+    }
+
+    // Adds a trace; a shared x series stays shared only for y pushed with push_y
+    pub fn push(&mut self, x: &'a [f64], y: &'a [f64]) {
+        self.unshare();
+        self.x.push(Cow::Borrowed(x));
+        self.y.push(Cow::Borrowed(y));
+    }
+
+    // Adds traces of many y against one x, e.g. the columns of another file;
+    // the series stays shared as long as this is its only x
+    pub fn push_shared_x<Y: AsRef<[f64]>>(&mut self, x: &'a [f64], y: &'a [Y]) {
+        if self.is_empty() {
+            self.x = vec![Cow::Borrowed(x)];
+            self.shared_x = true;
+        } else {
+            self.unshare();
+            self.x.extend(y.iter().map(|_| Cow::Borrowed(x)));
+        }
+        self.y.extend(y.iter().map(|y| Cow::Borrowed(y.as_ref())));
+    }
+
+    pub fn push_y(&mut self, y: &'a [f64]) {
+        if !self.shared_x {
+            panic!("push_y needs a series created with Series::shared_x");
+        }
+        self.y.push(Cow::Borrowed(y));
+    }
+
+    pub fn len(&self) -> usize {
+        self.y.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.y.is_empty()
+    }
+
+    pub fn x(&self, l: usize) -> &[f64] {
+        if self.shared_x { &self.x[0] } else { &self.x[l] }
+    }
+
+    pub fn y(&self, l: usize) -> &[f64] {
+        &self.y[l]
+    }
+
+    // Data columns as written by save_columns_to_file: x, y1, y2, ... for a shared x,
+    // otherwise x1, y1, x2, y2, ...
+    pub fn columns(&self) -> Vec<&[f64]> {
+        if self.shared_x {
+            std::iter::once(&self.x[0]).chain(self.y.iter()).map(|c| c.as_ref()).collect()
+        } else {
+            self.x.iter().zip(self.y.iter()).flat_map(|(x, y)| [x.as_ref(), y.as_ref()]).collect()
+        }
+    }
+
+    fn unshare(&mut self) {
+        if self.shared_x {
+            let x = self.x[0].clone();
+            self.x = vec![x; self.y.len()];
+            self.shared_x = false;
+        }
+    }
+}
+
+fn check_count(n_x: usize, n_y: usize) -> Result<(), String> {
+    if n_x != n_y {
+        return Err(format!("Got {} x series for {} y series", n_x, n_y));
+    }
+    Ok(())
+}
+
+// Numbers that can be plotted; f64 data is borrowed, everything else converted
+pub trait Scalar: Copy {
+    fn to_f64(self) -> f64;
+
+    fn to_column(values: &[Self]) -> Cow<'_, [f64]> {
+        Cow::Owned(values.iter().map(|v| v.to_f64()).collect())
+    }
+
+    fn into_column(values: Vec<Self>) -> Vec<f64> {
+        values.into_iter().map(|v| v.to_f64()).collect()
+    }
+}
+
+impl Scalar for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_column(values: &[f64]) -> Cow<'_, [f64]> {
+        Cow::Borrowed(values)
+    }
+
+    fn into_column(values: Vec<f64>) -> Vec<f64> {
+        values
+    }
+}
+
+macro_rules! scalar {
+    ($($t:ty),*) => {
+        $(impl Scalar for $t {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        })*
+    };
+}
+
+scalar!(f32, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+// One data column
+pub trait IntoColumn<'a> {
+    fn into_column(self) -> Cow<'a, [f64]>;
+}
+
+impl<'a, T: Scalar> IntoColumn<'a> for &'a [T] {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        T::to_column(self)
+    }
+}
+
+impl<'a, T: Scalar> IntoColumn<'a> for &'a Vec<T> {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        T::to_column(self)
+    }
+}
+
+impl<'a, T: Scalar, const N: usize> IntoColumn<'a> for &'a [T; N] {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        T::to_column(self)
+    }
+}
+
+impl<'a, T: Scalar> IntoColumn<'a> for Vec<T> {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        Cow::Owned(T::into_column(self))
+    }
+}
+
+impl<'a> IntoColumn<'a> for Cow<'a, [f64]> {
+    fn into_column(self) -> Cow<'a, [f64]> {
+        self
+    }
+}
+
+// Anything line_plot and save_columns_to_file take:
+// a Series, an (x, y) pair, a Vec or array of (x, y) pairs or Points.
+// Many y against one x are given as Series::shared_x(&x, &ys).
+// With the ndarray feature also ArrayView1 columns and Array2, see arrays.rs,
+// with the polars feature a DataFrame, see frames.rs.
+pub trait IntoSeries<'a> {
+    fn into_series(self) -> Series<'a>;
+}
+
+impl<'a> IntoSeries<'a> for Series<'a> {
+    fn into_series(self) -> Series<'a> {
+        self
+    }
+}
+
+impl<'a> IntoSeries<'a> for &Series<'a> {
+    fn into_series(self) -> Series<'a> {
+        self.clone()
+    }
+}
+
+impl<'a, X: IntoColumn<'a>, Y: IntoColumn<'a>> IntoSeries<'a> for (X, Y) {
+    fn into_series(self) -> Series<'a> {
+        Series::pairs(vec![self.0], vec![self.1])
+    }
+}
+
+impl<'a, X: IntoColumn<'a>, Y: IntoColumn<'a>> IntoSeries<'a> for Vec<(X, Y)> {
+    fn into_series(self) -> Series<'a> {
+        let (x, y): (Vec<X>, Vec<Y>) = self.into_iter().unzip();
+        Series::pairs(x, y)
+    }
+}
+
+impl<'a, X: IntoColumn<'a>, Y: IntoColumn<'a>, const N: usize> IntoSeries<'a> for [(X, Y); N] {
+    fn into_series(self) -> Series<'a> {
+        let (x, y): (Vec<X>, Vec<Y>) = self.into_iter().unzip();
+        Series::pairs(x, y)
+    }
+}
+
+// A single trace from an iterator of (x, y) points
+pub struct Points<I>(pub I);
+
+impl<'a, I, X, Y> IntoSeries<'a> for Points<I> where I: IntoIterator<Item = (X, Y)>, X: Scalar, Y: Scalar {
+    fn into_series(self) -> Series<'a> {
+        let (x, y): (Vec<f64>, Vec<f64>) = self.0.into_iter().map(|(x, y)| (x.to_f64(), y.to_f64())).unzip();
+        Series::pairs(vec![x], vec![y])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let x = vec![0.0, 1.0];
+        let ys = vec![vec![2.0, 3.0], vec![4.0, 5.0]];
+        let shared = Series::shared_x(&x, &ys);
+        assert_eq!(shared.columns(), vec![&x[..], &ys[0][..], &ys[1][..]]);
+        let pairs = Series::new(&ys, &ys).unwrap();
+        assert_eq!(pairs.columns(), vec![&ys[0][..], &ys[0][..], &ys[1][..], &ys[1][..]]);
+        assert_eq!((pairs.len(), pairs.x(1)), (2, &ys[1][..]));
+    }
+
+    #[test]
+    fn borrowed_not_copied() {
+        let x = vec![0.0, 1.0, 2.0];
+        let ys = vec![vec![1.0; 3], vec![2.0; 3]];
+        assert!(matches!((&x).into_column(), Cow::Borrowed(_)));
+        assert!(matches!((&vec![1.0f32]).into_column(), Cow::Owned(_)));
+        let mut series = Series::shared_x(&x, &ys);
+        assert!((0..2).all(|l| series.x(l).as_ptr() == x.as_ptr() && series.y(l).as_ptr() == ys[l].as_ptr()));
+        // still the caller's x after pushing a trace with another x
+        series.push(&ys[0], &x);
+        assert!(series.x(1).as_ptr() == x.as_ptr() && series.x(2).as_ptr() == ys[0].as_ptr());
+    }
+
+    #[test]
+    fn groups() {
+        let (x1, x2) = (vec![0.0, 1.0], vec![5.0]);
+        let (ys1, ys2) = (vec![vec![1.0; 2], vec![2.0; 2]], vec![vec![3.0]]);
+        let mut series = Series::empty();
+        series.push_shared_x(&x1, &ys1);
+        assert_eq!(series.columns().len(), 3);
+        series.push_shared_x(&x2, &ys2);
+        assert_eq!(series.len(), 3);
+        assert!(series.x(1).as_ptr() == x1.as_ptr() && series.x(2).as_ptr() == x2.as_ptr());
+        assert_eq!(series.columns(), vec![&x1[..], &ys1[0][..], &x1[..], &ys1[1][..], &x2[..], &ys2[0][..]]);
+    }
+
+    #[test]
+    fn counts() {
+        let x = vec![0.0, 1.0];
+        assert!(Series::new(&[&x], &[&x, &x]).is_err());
+        assert!(Series::from_columns(vec![&x, &x], vec![&x, &x, &x]).is_err());
+        let series = Series::from_columns(vec![&x], vec![&x, &x, &x]).unwrap();
+        assert_eq!(series.columns().len(), 4);
+        assert!(Series::from_columns(Vec::<&Vec<f64>>::new(), vec![&x]).is_err());
+    }
+}
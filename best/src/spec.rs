@@ -0,0 +1,470 @@
+// Column specs: series derived from the raw columns of a data file at load time, e.g.
+// "x = 1e4*$1; y = $3/$2; y = log10($5)". Columns are $1, $2, ... or $name and ${long name}
+// for the header names. Assignments are separated by ; or new lines, y may be given more than once.
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::file::read_columns_from_file;
+use crate::series::Series;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSpec {
+    x: Expr,
+    y: Vec<Expr>,
+    // the y expressions as written, for legends
+    y_text: Vec<String>,
+}
+
+// Position counted in characters from 1, 0 when the error is not at one place in the spec
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.position == 0 {
+            write!(f, "{} in the column spec", self.message)
+        } else {
+            write!(f, "{} at position {} of the column spec", self.message, self.position)
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    // column number and position in the spec, 0 for default columns
+    Column(usize, usize),
+    Named(String, usize),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl ColumnSpec {
+    // Without an x assignment x is $1, without y every other column is a y
+    pub fn parse(text: &str) -> Result<ColumnSpec, SpecError> {
+        let mut x = None;
+        let mut y = Vec::new();
+        let mut y_text = Vec::new();
+        let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+        loop {
+            parser.skip_separators();
+            if parser.pos == parser.chars.len() {
+                break;
+            }
+            let start = parser.pos;
+            let target = parser.identifier();
+            parser.expect('=')?;
+            let expr_start = parser.pos;
+            let expr = parser.expr()?;
+            match target.as_str() {
+                "x" if x.is_none() => x = Some(expr),
+                "x" => return Err(SpecError { position: start + 1, message: "x is assigned twice".to_string() }),
+                "y" => {
+                    y.push(expr);
+                    y_text.push(parser.chars[expr_start..parser.pos].iter().collect::<String>().trim().to_string());
+                },
+                _ => return Err(SpecError { position: start + 1, message: format!("expected x = or y =, found {:?}", target) }),
+            }
+            parser.skip_spaces();
+            if parser.pos < parser.chars.len() && !matches!(parser.chars[parser.pos], ';' | '\n') {
+                return Err(parser.error("expected ; or the end of the spec"));
+            }
+        }
+        Ok(ColumnSpec { x: x.unwrap_or(Expr::Column(1, 0)), y, y_text })
+    }
+
+    // x and the y series from the columns and their header names
+    pub fn apply(&self, names: &[String], columns: &[Vec<f64>]) -> Result<(Vec<f64>, Vec<Vec<f64>>), SpecError> {
+        let x = eval_column(&self.x, names, columns)?;
+        let y = self.y_columns(names, columns.len()).iter()
+            .map(|expr| eval_column(expr, names, columns))
+            .collect::<Result<_, _>>()?;
+        Ok((x, y))
+    }
+
+    // A label for every y series: the expression as written, or the header name of a default column
+    pub fn y_labels(&self, names: &[String], n_columns: usize) -> Vec<String> {
+        if !self.y.is_empty() {
+            return self.y_text.clone();
+        }
+        self.y_columns(names, n_columns).iter().map(|expr| match expr {
+            Expr::Column(k, _) => names.get(k - 1).cloned().unwrap_or_else(|| format!("${}", k)),
+            _ => String::new(),
+        }).collect()
+    }
+
+    // the given y, or every column that x doesn't use
+    fn y_columns(&self, names: &[String], n_columns: usize) -> Vec<Expr> {
+        if !self.y.is_empty() {
+            return self.y.clone();
+        }
+        let mut x_columns = Vec::new();
+        columns_used(&self.x, names, &mut x_columns);
+        (1..=n_columns).filter(|k| !x_columns.contains(k)).map(|k| Expr::Column(k, 0)).collect()
+    }
+}
+
+impl FromStr for ColumnSpec {
+    type Err = SpecError;
+
+    fn from_str(text: &str) -> Result<ColumnSpec, SpecError> {
+        ColumnSpec::parse(text)
+    }
+}
+
+// Reads a data file with read_columns_from_file and applies the spec, ready for line_plot
+pub fn load_series<P: AsRef<Path>>(path: P, spec: &ColumnSpec) -> Series<'static> {
+    let path = path.as_ref();
+    let (names, columns) = read_columns_from_file(path);
+    let (x, y) = spec.apply(&names, &columns).unwrap_or_else(|e| panic!("Error in {:?}: {}", path, e));
+    Series::shared_x(x, y)
+}
+
+fn eval_column(expr: &Expr, names: &[String], columns: &[Vec<f64>]) -> Result<Vec<f64>, SpecError> {
+    let expr = resolve(expr, names, columns.len())?;
+    let rows = max_rows(&expr, columns);
+    Ok((0..rows).map(|i| eval(&expr, i, columns)).collect())
+}
+
+// Names replaced by column numbers, every column checked to exist
+fn resolve(expr: &Expr, names: &[String], n_columns: usize) -> Result<Expr, SpecError> {
+    Ok(match expr {
+        Expr::Named(name, position) => {
+            let k = names.iter().position(|n| n == name)
+                .ok_or_else(|| SpecError { position: *position, message: format!("no column named {:?}", name) })?;
+            Expr::Column(k + 1, *position)
+        },
+        Expr::Column(k, position) if *k == 0 || *k > n_columns => {
+            return Err(SpecError { position: *position, message: format!("column ${} doesn't exist, the file has {} columns", k, n_columns) });
+        },
+        Expr::Neg(a) => Expr::Neg(Box::new(resolve(a, names, n_columns)?)),
+        Expr::Binary(op, a, b) => Expr::Binary(*op, Box::new(resolve(a, names, n_columns)?), Box::new(resolve(b, names, n_columns)?)),
+        Expr::Call(f, args) => Expr::Call(f.clone(), args.iter().map(|a| resolve(a, names, n_columns)).collect::<Result<_, _>>()?),
+        expr => expr.clone(),
+    })
+}
+
+// Numbers of the columns in expr, names that aren't in the header left out
+fn columns_used(expr: &Expr, names: &[String], columns: &mut Vec<usize>) {
+    match expr {
+        Expr::Column(k, _) => columns.push(*k),
+        Expr::Named(name, _) => columns.extend(names.iter().position(|n| n == name).map(|k| k + 1)),
+        Expr::Neg(a) => columns_used(a, names, columns),
+        Expr::Binary(_, a, b) => {
+            columns_used(a, names, columns);
+            columns_used(b, names, columns);
+        },
+        Expr::Call(_, args) => args.iter().for_each(|a| columns_used(a, names, columns)),
+        Expr::Number(_) => {},
+    }
+}
+
+// Rows of the longest column used, a spec of constants only gives one row
+fn max_rows(expr: &Expr, columns: &[Vec<f64>]) -> usize {
+    match expr {
+        Expr::Column(k, _) => columns[k - 1].len(),
+        Expr::Neg(a) => max_rows(a, columns),
+        Expr::Binary(_, a, b) => max_rows(a, columns).max(max_rows(b, columns)),
+        Expr::Call(_, args) => args.iter().map(|a| max_rows(a, columns)).max().unwrap_or(1),
+        _ => 1,
+    }
+}
+
+fn eval(expr: &Expr, i: usize, columns: &[Vec<f64>]) -> f64 {
+    match expr {
+        Expr::Number(v) => *v,
+        Expr::Column(k, _) => columns[k - 1].get(i).cloned().unwrap_or(f64::NAN),
+        Expr::Named(..) => f64::NAN,
+        Expr::Neg(a) => -eval(a, i, columns),
+        Expr::Binary(op, a, b) => {
+            let (a, b) = (eval(a, i, columns), eval(b, i, columns));
+            match op {
+                '+' => a + b,
+                '-' => a - b,
+                '*' => a * b,
+                '/' => a / b,
+                _ => a.powf(b),
+            }
+        },
+        Expr::Call(f, args) => {
+            let a: Vec<f64> = args.iter().map(|a| eval(a, i, columns)).collect();
+            call(f, &a)
+        },
+    }
+}
+
+// Functions and their number of arguments
+const FUNCTIONS: [(&str, usize); 19] = [
+    ("log10", 1), ("log2", 1), ("ln", 1), ("log", 1), ("exp", 1), ("sqrt", 1), ("abs", 1),
+    ("sin", 1), ("cos", 1), ("tan", 1), ("asin", 1), ("acos", 1), ("atan", 1),
+    ("sinh", 1), ("cosh", 1), ("tanh", 1), ("atan2", 2), ("min", 2), ("max", 2),
+];
+
+fn call(f: &str, a: &[f64]) -> f64 {
+    match f {
+        "log10" => a[0].log10(),
+        "log2" => a[0].log2(),
+        "ln" | "log" => a[0].ln(),
+        "exp" => a[0].exp(),
+        "sqrt" => a[0].sqrt(),
+        "abs" => a[0].abs(),
+        "sin" => a[0].sin(),
+        "cos" => a[0].cos(),
+        "tan" => a[0].tan(),
+        "asin" => a[0].asin(),
+        "acos" => a[0].acos(),
+        "atan" => a[0].atan(),
+        "sinh" => a[0].sinh(),
+        "cosh" => a[0].cosh(),
+        "tanh" => a[0].tanh(),
+        "atan2" => a[0].atan2(a[1]),
+        "min" => a[0].min(a[1]),
+        _ => a[0].max(a[1]),
+    }
+}
+
+// Recursive descent: expr = term (+|- term)*, term = unary (*|/ unary)*,
+// unary = - unary | power, power = atom (^ unary)?
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> SpecError {
+        SpecError { position: self.pos + 1, message: message.to_string() }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.pos < self.chars.len() && matches!(self.chars[self.pos], ' ' | '\t' | '\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_whitespace() || self.chars[self.pos] == ';') {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), SpecError> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", c)))
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        self.skip_spaces();
+        let start = self.pos;
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn expr(&mut self) -> Result<Expr, SpecError> {
+        let mut left = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, SpecError> {
+        let mut left = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, SpecError> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            },
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            },
+            _ => {
+                let base = self.atom()?;
+                if self.peek() == Some('^') {
+                    self.pos += 1;
+                    return Ok(Expr::Binary('^', Box::new(base), Box::new(self.unary()?)));
+                }
+                Ok(base)
+            },
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, SpecError> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            },
+            Some('$') => self.column(),
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => {
+                let start = self.pos;
+                let name = self.identifier();
+                match name.as_str() {
+                    "pi" => return Ok(Expr::Number(std::f64::consts::PI)),
+                    "e" => return Ok(Expr::Number(std::f64::consts::E)),
+                    _ => {},
+                }
+                let arity = FUNCTIONS.iter().find(|(f, _)| *f == name).map(|(_, n)| *n)
+                    .ok_or(SpecError { position: start + 1, message: format!("unknown function {:?}", name) })?;
+                self.expect('(')?;
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(',') {
+                    self.pos += 1;
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                if args.len() != arity {
+                    return Err(SpecError { position: start + 1, message: format!("{} takes {} argument(s)", name, arity) });
+                }
+                Ok(Expr::Call(name, args))
+            },
+            _ => Err(self.error("expected a number, column, function or (")),
+        }
+    }
+
+    // $3, $name or ${name with spaces}
+    fn column(&mut self) -> Result<Expr, SpecError> {
+        let start = self.pos;
+        self.pos += 1;
+        if self.chars.get(self.pos) == Some(&'{') {
+            let end = self.chars[self.pos..].iter().position(|&c| c == '}')
+                .ok_or(SpecError { position: start + 1, message: "unclosed ${".to_string() })?;
+            let name: String = self.chars[self.pos + 1..self.pos + end].iter().collect();
+            self.pos += end + 1;
+            return Ok(Expr::Named(name, start + 1));
+        }
+        let name: String = {
+            let from = self.pos;
+            while self.pos < self.chars.len() && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_') {
+                self.pos += 1;
+            }
+            self.chars[from..self.pos].iter().collect()
+        };
+        if name.is_empty() {
+            return Err(SpecError { position: start + 1, message: "expected a column number or name after $".to_string() });
+        }
+        Ok(match name.parse() {
+            Ok(k) => Expr::Column(k, start + 1),
+            Err(_) => Expr::Named(name, start + 1),
+        })
+    }
+
+    // also Fortran exponents, 1.0D-03
+    fn number(&mut self) -> Result<Expr, SpecError> {
+        let start = self.pos;
+        let mut text = String::new();
+        while self.pos < self.chars.len() && (self.chars[self.pos].is_ascii_digit() || self.chars[self.pos] == '.') {
+            text.push(self.chars[self.pos]);
+            self.pos += 1;
+        }
+        if self.pos < self.chars.len() && matches!(self.chars[self.pos], 'e' | 'E' | 'd' | 'D') {
+            let mut exponent = String::from("e");
+            let mut j = self.pos + 1;
+            if j < self.chars.len() && matches!(self.chars[j], '+' | '-') {
+                exponent.push(self.chars[j]);
+                j += 1;
+            }
+            let digits_start = j;
+            while j < self.chars.len() && self.chars[j].is_ascii_digit() {
+                exponent.push(self.chars[j]);
+                j += 1;
+            }
+            if j > digits_start {
+                text.push_str(&exponent);
+                self.pos = j;
+            }
+        }
+        text.parse().map(Expr::Number)
+            .map_err(|_| SpecError { position: start + 1, message: format!("invalid number {:?}", text) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn expressions() {
+        let spec = ColumnSpec::parse("x = 1e4*$1; y = $3/$2\ny = -2^2 + max($1, 1.5D0)").unwrap();
+        let columns = vec![vec![1.0, 2.0], vec![2.0, 4.0], vec![1.0, 1.0]];
+        let (x, y) = spec.apply(&[], &columns).unwrap();
+        assert_eq!(x, vec![1e4, 2e4]);
+        assert_eq!(y, vec![vec![0.5, 0.25], vec![-2.5, -2.0]]);
+        assert_eq!(spec.y_labels(&[], 3), vec!["$3/$2", "-2^2 + max($1, 1.5D0)"]);
+    }
+
+    #[test]
+    fn names_and_defaults() {
+        let header = names(&["t", "U", "long name"]);
+        let columns = vec![vec![0.0], vec![1.0], vec![2.0]];
+        let spec = ColumnSpec::parse("x = $t; y = ${long name} - $U").unwrap();
+        assert_eq!(spec.apply(&header, &columns).unwrap(), (vec![0.0], vec![vec![1.0]]));
+
+        let spec = ColumnSpec::parse("").unwrap();
+        assert_eq!(spec.apply(&header, &columns).unwrap().1, vec![vec![1.0], vec![2.0]]);
+        assert_eq!(spec.y_labels(&header, 3), vec!["U", "long name"]);
+    }
+
+    #[test]
+    fn default_y_leaves_out_the_columns_of_x() {
+        let header = names(&["t", "U", "I"]);
+        let columns = vec![vec![1.0], vec![2.0], vec![3.0]];
+        let spec = ColumnSpec::parse("x = 1e4*$1").unwrap();
+        assert_eq!(spec.apply(&header, &columns).unwrap().1, vec![vec![2.0], vec![3.0]]);
+        assert_eq!(spec.y_labels(&header, 3), vec!["U", "I"]);
+
+        let spec = ColumnSpec::parse("x = $U/$I").unwrap();
+        assert_eq!(spec.y_labels(&header, 3), vec!["t"]);
+        let spec = ColumnSpec::parse("x = 2").unwrap();
+        assert_eq!(spec.y_labels(&header, 3).len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        let position = |text: &str| ColumnSpec::parse(text).unwrap_err().position;
+        assert_eq!(position("x = $1; x = $2"), 9);
+        assert_eq!(position("y = foo($1)"), 5);
+        assert_eq!(position("y = atan2($1)"), 5);
+        assert_eq!(position("y = ($1"), 8);
+        assert_eq!(position("z = $1"), 1);
+        let spec = ColumnSpec::parse("y = $1 + $nope").unwrap();
+        let error = spec.apply(&[], &[vec![1.0]]).unwrap_err();
+        assert_eq!(error.position, 10);
+        let error = ColumnSpec::parse("y = $4").unwrap().apply(&[], &[vec![1.0]]).unwrap_err();
+        assert_eq!(error.message, "column $4 doesn't exist, the file has 1 columns");
+    }
+}
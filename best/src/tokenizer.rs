@@ -0,0 +1,449 @@
+// Parsing text data from solvers and instruments: Fortran D exponents, decimal commas,
+// inf and nan spellings and trailing units, with the delimiter and decimal mark detected
+use std::fmt;
+use std::io::{self, BufRead, ErrorKind};
+use std::path::Path;
+
+use crate::compression::open_reader;
+
+// data lines looked at to detect the delimiter and the decimal mark
+const DETECT_LINES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delimiter {
+    Auto,
+    // any run of spaces and tabs
+    Whitespace,
+    Comma,
+    Semicolon,
+    Tab,
+    Char(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecimalMark {
+    Auto,
+    Point,
+    Comma,
+}
+
+pub struct ParseOptions {
+    pub delimiter: Delimiter,
+    pub decimal: DecimalMark,
+    // lines starting with it are skipped, the first one before any data may hold column names
+    pub comment: char,
+    // lines skipped at the start of the file, e.g. an instrument header
+    pub skip_lines: usize,
+    // drop units after numbers, e.g. "1.5 mV" or "1.5mV"
+    pub units: bool,
+    // skip a last line without a newline, which a running program may still be writing
+    pub complete_lines: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        ParseOptions {
+            delimiter: Delimiter::Auto,
+            decimal: DecimalMark::Auto,
+            comment: '#',
+            skip_lines: 0,
+            units: true,
+            complete_lines: false,
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    // the file can't be opened or read
+    Io(io::Error),
+    // a malformed token, line and column counted from 1
+    Token { line: usize, column: usize, token: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "{}", e),
+            ParseError::Token { line, column, token } => write!(f, "can't parse {:?} at line {}, column {}", token, line, column),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+// A whole token as a number, e.g. "1.0D-03", "0,5" with a decimal comma, "-Infinity" or "1.#QNAN".
+// DecimalMark::Auto takes either mark.
+pub fn parse_number(token: &str, decimal: DecimalMark) -> Option<f64> {
+    match parse_prefix(token.trim(), decimal) {
+        Some((value, len)) if len == token.trim().len() => Some(value),
+        _ => None,
+    }
+}
+
+// Header names, if any, and the columns; short rows are padded with NaN and empty fields are NaN.
+// A first line without any number is taken as the column names as well.
+// Lines like "1,5" are two comma separated columns, which is what they usually are;
+// set DecimalMark::Comma to read them as one column with a decimal comma.
+// With the arrow feature, .arrow, .ipc, .feather and .parquet files are read with read_arrow
+// and the options don't apply.
+pub fn read_columns_with<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<(Vec<String>, Vec<Vec<f64>>), ParseError> {
+    let path = path.as_ref();
+    #[cfg(feature = "arrow")]
+    if crate::columnar::is_columnar(path) {
+        let table = crate::columnar::read_arrow(path)?;
+        return Ok((table.names, table.columns));
+    }
+    let mut reader = open_reader(path)?;
+    let lines = std::iter::from_fn(move || {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) if options.complete_lines && !line.ends_with('\n') => None,
+            Ok(_) => {
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Some(Ok(line))
+            },
+            Err(e) => Some(Err(e)),
+        }
+    });
+    let mut lines = lines.enumerate().skip(options.skip_lines).map_while(|(i, line)| match line {
+        Ok(line) => Some(Ok((i + 1, line))),
+        // the unfinished end of a compressed file that a ColumnWriter is still writing
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+        Err(e) => Some(Err(io::Error::new(e.kind(), format!("can't read line {}: {}", i + 1, e)))),
+    });
+
+    // the first data lines are kept back until the format is known
+    let mut pending = Vec::new();
+    for line in lines.by_ref() {
+        let (number, line) = line?;
+        let data = !line.trim().is_empty() && !line.trim_start().starts_with(options.comment);
+        pending.push((number, line));
+        if data && pending.len() >= DETECT_LINES {
+            break;
+        }
+    }
+    let sample: Vec<&str> = pending.iter()
+        .map(|(_, line)| line.as_str())
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with(options.comment))
+        .collect();
+    let (delimiter, decimal) = detect(&sample, options.delimiter, options.decimal);
+
+    let mut names = Vec::new();
+    let mut columns: Vec<Vec<f64>> = Vec::new();
+    let mut rows = 0;
+    for line in pending.into_iter().map(Ok).chain(lines) {
+        let (number, line) = line?;
+        let trimmed = line.trim();
+        if let Some(comment) = trimmed.strip_prefix(options.comment) {
+            if rows == 0 && names.is_empty() {
+                names = comment.split_whitespace().map(|s| s.to_string()).collect();
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        let fields = split(&line, delimiter);
+        if rows == 0 && fields.iter().all(|(_, token)| parse_prefix(token, decimal).is_none()) {
+            names = fields.iter().map(|(_, token)| token.to_string()).collect();
+            continue;
+        }
+        let row = parse_fields(&line, &fields, number, decimal, options.units)?;
+        for (k, value) in row.into_iter().enumerate() {
+            if k == columns.len() {
+                columns.push(vec![f64::NAN; rows]);
+            }
+            columns[k].push(value);
+        }
+        rows += 1;
+        for column in columns.iter_mut() {
+            column.resize(rows, f64::NAN);
+        }
+    }
+    Ok((names, columns))
+}
+
+fn parse_fields(line: &str, fields: &[(usize, &str)], number: usize, decimal: DecimalMark, units: bool) -> Result<Vec<f64>, ParseError> {
+    let mut row = Vec::with_capacity(fields.len());
+    let mut after_number = false;
+    for &(offset, token) in fields {
+        if token.is_empty() {
+            row.push(f64::NAN);
+            after_number = false;
+            continue;
+        }
+        let error = || ParseError::Token { line: number, column: line[..offset].chars().count() + 1, token: token.to_string() };
+        match parse_prefix(token, decimal) {
+            Some((value, len)) if len == token.len() || (units && is_unit(&token[len..])) => {
+                row.push(value);
+                after_number = true;
+            },
+            // a unit in its own field, e.g. the mV of "1.5 mV"; digits there are more likely a typo
+            None if units && after_number && is_unit(token) && !token.contains(|c: char| c.is_ascii_digit()) => after_number = false,
+            _ => return Err(error()),
+        }
+    }
+    Ok(row)
+}
+
+fn is_unit(text: &str) -> bool {
+    let text = text.trim_start();
+    text.chars().next().map(|c| c.is_alphabetic() || "%°µΩ[(/".contains(c)).unwrap_or(false)
+}
+
+// Fields with their byte offsets; fields between delimiters other than whitespace may be empty
+fn split(line: &str, delimiter: Delimiter) -> Vec<(usize, &str)> {
+    let separator = match delimiter {
+        Delimiter::Comma => ',',
+        Delimiter::Semicolon => ';',
+        Delimiter::Tab => '\t',
+        Delimiter::Char(c) => c,
+        Delimiter::Whitespace | Delimiter::Auto => {
+            let mut fields = Vec::new();
+            let mut start = None;
+            for (i, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+                match (c.is_whitespace(), start) {
+                    (false, None) => start = Some(i),
+                    (true, Some(s)) => {
+                        fields.push((s, &line[s..i]));
+                        start = None;
+                    },
+                    _ => {},
+                }
+            }
+            return fields;
+        },
+    };
+    let mut fields = Vec::new();
+    let mut start = 0;
+    for piece in line.split(separator) {
+        let offset = start + (piece.len() - piece.trim_start().len());
+        fields.push((offset, piece.trim()));
+        start += piece.len() + separator.len_utf8();
+    }
+    // a delimiter at the end of the line doesn't start another field
+    if fields.len() > 1 && fields.last().map(|(_, token)| token.is_empty()).unwrap_or(false) {
+        fields.pop();
+    }
+    fields
+}
+
+// Delimiter and decimal mark used by the sample lines, Auto settings replaced
+fn detect(sample: &[&str], delimiter: Delimiter, decimal: DecimalMark) -> (Delimiter, DecimalMark) {
+    let in_every_line = |c: char| !sample.is_empty() && sample.iter().all(|line| line.contains(c));
+    // "0,5 1,25" can only have decimal commas, but "1,5" and "1,2,3" are taken as comma separated
+    let whitespace_fields = sample.iter().all(|line| split(line, Delimiter::Whitespace).len() > 1);
+    let delimiter = match delimiter {
+        Delimiter::Auto => {
+            if in_every_line(';') {
+                Delimiter::Semicolon
+            } else if in_every_line('\t') {
+                Delimiter::Tab
+            } else if in_every_line(',') && decimal != DecimalMark::Comma
+                && !(whitespace_fields && comma_decimals(sample, Delimiter::Whitespace)) {
+                Delimiter::Comma
+            } else {
+                Delimiter::Whitespace
+            }
+        },
+        delimiter => delimiter,
+    };
+    let decimal = match decimal {
+        DecimalMark::Auto if delimiter != Delimiter::Comma && comma_decimals(sample, delimiter) => DecimalMark::Comma,
+        DecimalMark::Auto => DecimalMark::Point,
+        decimal => decimal,
+    };
+    (delimiter, decimal)
+}
+
+// Some fields have a comma and all of those parse with a decimal comma
+fn comma_decimals(sample: &[&str], delimiter: Delimiter) -> bool {
+    let tokens = || sample.iter().flat_map(|line| split(line, delimiter)).map(|(_, token)| token);
+    tokens().any(|token| token.contains(',')) && tokens()
+        .filter(|token| token.contains(','))
+        .all(|token| token.matches(',').count() == 1 && matches!(parse_prefix(token, DecimalMark::Comma),
+            Some((_, len)) if len == token.len() || is_unit(&token[len..])))
+}
+
+// The number at the start of token and its length in bytes
+fn parse_prefix(token: &str, decimal: DecimalMark) -> Option<(f64, usize)> {
+    let bytes = token.as_bytes();
+    let mut i = 0;
+    let mut normalized = String::new();
+    if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+        normalized.push(bytes[i] as char);
+        i += 1;
+    }
+    let negative = normalized == "-";
+
+    let rest = token[i..].to_ascii_lowercase();
+    for (spelling, value) in [("infinity", f64::INFINITY), ("inf", f64::INFINITY), ("nan", f64::NAN)] {
+        if let Some(after) = rest.strip_prefix(spelling) {
+            let mut len = i + spelling.len();
+            // nan(ind), nan(snan) and the like
+            if value.is_nan() && after.starts_with('(') {
+                len += after.find(')')? + 1;
+            }
+            return Some((if negative { -value } else { value }, len));
+        }
+    }
+
+    let digits = |i: &mut usize, normalized: &mut String| {
+        let start = *i;
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            normalized.push(bytes[*i] as char);
+            *i += 1;
+        }
+        *i - start
+    };
+    let mut count = digits(&mut i, &mut normalized);
+    let is_mark = |b: u8| match decimal {
+        DecimalMark::Point => b == b'.',
+        DecimalMark::Comma => b == b',',
+        DecimalMark::Auto => b == b'.' || b == b',',
+    };
+    let mut fraction = false;
+    if i < bytes.len() && is_mark(bytes[i]) {
+        normalized.push('.');
+        i += 1;
+        fraction = true;
+        count += digits(&mut i, &mut normalized);
+    }
+    if count == 0 {
+        return None;
+    }
+
+    // MSVC spellings 1.#INF, 1.#IND, 1.#QNAN
+    let upper = token[i..].to_ascii_uppercase();
+    for (spelling, value) in [("#INF", f64::INFINITY), ("#IND", f64::NAN), ("#QNAN", f64::NAN), ("#SNAN", f64::NAN)] {
+        if upper.starts_with(spelling) {
+            return Some((if negative { -value } else { value }, i + spelling.len()));
+        }
+    }
+
+    // exponent with e, Fortran d or q, or a bare sign as in Fortran's 1.234567+100
+    let mut j = i;
+    if j < bytes.len() && b"eEdDqQ".contains(&bytes[j]) {
+        j += 1;
+    } else if !(fraction && j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-')) {
+        j = bytes.len() + 1;
+    }
+    if j <= bytes.len() {
+        let mut exponent = String::from("e");
+        if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+            exponent.push(bytes[j] as char);
+            j += 1;
+        }
+        if digits(&mut j, &mut exponent) > 0 {
+            normalized.push_str(&exponent);
+            i = j;
+        }
+    }
+    normalized.parse().ok().map(|value| (value, i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn read(name: &str, text: &str, options: &ParseOptions) -> Result<(Vec<String>, Vec<Vec<f64>>), ParseError> {
+        let path = std::env::temp_dir().join(format!("tokenizer-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let result = read_columns_with(&path, options);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn fortran_exponents() {
+        assert_eq!(parse_number("1.0D-03", DecimalMark::Auto), Some(1.0e-3));
+        assert_eq!(parse_number("-2.5d+2", DecimalMark::Point), Some(-250.0));
+        assert_eq!(parse_number("1.5Q3", DecimalMark::Point), Some(1500.0));
+        assert_eq!(parse_number("1.234567+100", DecimalMark::Point), Some(1.234567e100));
+        assert_eq!(parse_number("0,5E2", DecimalMark::Comma), Some(50.0));
+    }
+
+    #[test]
+    fn infinities_and_nans() {
+        assert_eq!(parse_number("inf", DecimalMark::Auto), Some(f64::INFINITY));
+        assert_eq!(parse_number("-Infinity", DecimalMark::Auto), Some(f64::NEG_INFINITY));
+        assert_eq!(parse_number("-1.#INF", DecimalMark::Auto), Some(f64::NEG_INFINITY));
+        for token in ["NaN", "nan(ind)", "1.#QNAN", "-1.#IND"] {
+            assert!(parse_number(token, DecimalMark::Auto).unwrap().is_nan(), "{}", token);
+        }
+        assert_eq!(parse_number("information", DecimalMark::Auto), None);
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(parse_prefix("1.5mV", DecimalMark::Point), Some((1.5, 3)));
+        assert_eq!(parse_prefix("2e3 Hz", DecimalMark::Point), Some((2000.0, 3)));
+        assert_eq!(parse_prefix("1,5", DecimalMark::Point), Some((1.0, 1)));
+        assert_eq!(parse_prefix("x1", DecimalMark::Point), None);
+        assert_eq!(parse_prefix(".", DecimalMark::Point), None);
+    }
+
+    #[test]
+    fn detection() {
+        let auto = |sample: &[&str]| detect(sample, Delimiter::Auto, DecimalMark::Auto);
+        assert_eq!(auto(&["1.5 2.5", "3 4"]), (Delimiter::Whitespace, DecimalMark::Point));
+        assert_eq!(auto(&["1.5,2.5", "3,4"]), (Delimiter::Comma, DecimalMark::Point));
+        assert_eq!(auto(&["1,5;2", "3;4,25"]), (Delimiter::Semicolon, DecimalMark::Comma));
+        assert_eq!(auto(&["0,5 1,25", "2 3,5"]), (Delimiter::Whitespace, DecimalMark::Comma));
+        // ambiguous, comma separated integers unless the decimal comma is asked for
+        assert_eq!(auto(&["1,5", "2,25"]), (Delimiter::Comma, DecimalMark::Point));
+        assert_eq!(auto(&["1,2", "3,4"]), (Delimiter::Comma, DecimalMark::Point));
+        assert_eq!(detect(&["1,5", "2,25"], Delimiter::Auto, DecimalMark::Comma), (Delimiter::Whitespace, DecimalMark::Comma));
+        assert_eq!(auto(&["x,y", "1,2", "3,4"]), (Delimiter::Comma, DecimalMark::Point));
+        assert_eq!(auto(&["1,2,3"]), (Delimiter::Comma, DecimalMark::Point));
+        assert_eq!(detect(&["1,2", "3,4"], Delimiter::Comma, DecimalMark::Auto), (Delimiter::Comma, DecimalMark::Point));
+    }
+
+    #[test]
+    fn integer_csv_and_decimal_commas() {
+        let (_, columns) = read("integer-csv", "1,2\n3,4\n", &ParseOptions::new()).unwrap();
+        assert_eq!(columns, vec![vec![1.0, 3.0], vec![2.0, 4.0]]);
+        let mut options = ParseOptions::new();
+        options.decimal = DecimalMark::Comma;
+        let (_, columns) = read("decimal-comma", "1,5\n2,25\n", &options).unwrap();
+        assert_eq!(columns, vec![vec![1.5, 2.25]]);
+    }
+
+    #[test]
+    fn headers_and_units() {
+        let (names, columns) = read("header", "# t U\n0 1.5 mV\n1 2.5mV\n", &ParseOptions::new()).unwrap();
+        assert_eq!(names, vec!["t", "U"]);
+        assert_eq!(columns, vec![vec![0.0, 1.0], vec![1.5, 2.5]]);
+
+        let (names, columns) = read("csv-header", "time,voltage\n0,1\n1,\n", &ParseOptions::new()).unwrap();
+        assert_eq!(names, vec!["time", "voltage"]);
+        assert_eq!(columns[0], vec![0.0, 1.0]);
+        assert!(columns[1][1].is_nan());
+    }
+
+    #[test]
+    fn errors() {
+        match read("malformed", "1 2\n3 x4\n", &ParseOptions::new()) {
+            Err(ParseError::Token { line, column, token }) => assert_eq!((line, column, token.as_str()), (2, 3, "x4")),
+            other => panic!("{:?}", other),
+        }
+        let missing = read_columns_with("/nonexistent/data.dat", &ParseOptions::new());
+        assert!(matches!(missing, Err(ParseError::Io(e)) if e.kind() == ErrorKind::NotFound));
+    }
+}
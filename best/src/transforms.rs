@@ -0,0 +1,220 @@
+// Smoothing and filtering of evenly sampled series, e.g. before plotting or saving
+use plotly::common::{Marker, MarkerSymbol, Mode};
+use plotly::Scatter;
+use std::borrow::Cow;
+
+use crate::fitting::invert;
+use crate::plot::{color, make_layout, make_trace, write_plot, PlotPar, Style};
+use crate::render::{Figure, Trace};
+use crate::series::IntoSeries;
+
+// How windows are filled past the ends of the series
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    // mirror about the end point: y[-k] = y[k]
+    Reflect,
+    // repeat the end point
+    Nearest,
+    // use only the points inside the series; Savitzky-Golay fits the end window instead
+    Shrink,
+}
+
+// The windowed filters take an odd window centred on every point and return an error for an
+// even one, 0 included. Windows may be longer than the series.
+
+// Mean over the window
+pub fn moving_average(y: &[f64], window: usize, edge: Edge) -> Result<Vec<f64>, String> {
+    check_window("moving_average", window)?;
+    let half = (window / 2) as isize;
+    let weights = vec![1.0; 2 * half as usize + 1];
+    Ok(convolve(y, &weights, half, edge))
+}
+
+// Gaussian kernel with standard deviation sigma in samples, truncated at 4 sigma
+pub fn gaussian(y: &[f64], sigma: f64, edge: Edge) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return y.to_vec();
+    }
+    let half = (4.0 * sigma).ceil() as isize;
+    let weights: Vec<f64> = (-half..=half).map(|k| (-0.5 * (k as f64 / sigma).powi(2)).exp()).collect();
+    convolve(y, &weights, half, edge)
+}
+
+// Median over the window
+pub fn median(y: &[f64], window: usize, edge: Edge) -> Result<Vec<f64>, String> {
+    check_window("median", window)?;
+    let half = (window / 2) as isize;
+    let n = y.len();
+    let mut values = Vec::with_capacity(2 * half as usize + 1);
+    Ok((0..n).map(|i| {
+        values.clear();
+        for k in -half..=half {
+            if let Some(j) = edge_index(i as isize + k, n, edge) {
+                values.push(y[j]);
+            }
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let m = values.len();
+        if m % 2 == 1 { values[m / 2] } else { 0.5 * (values[m / 2 - 1] + values[m / 2]) }
+    }).collect())
+}
+
+// A centred window has the point and as many on either side
+fn check_window(filter: &str, window: usize) -> Result<(), String> {
+    if window.is_multiple_of(2) {
+        return Err(format!("The window of {} must be odd, got {}", filter, window));
+    }
+    Ok(())
+}
+
+// Local least squares polynomial of the given order over the window, which must be longer than
+// the order. A window longer than the series is cut to the series; when that leaves no more
+// points than the order the polynomial goes through all of them and y is returned as it is.
+pub fn savitzky_golay(y: &[f64], window: usize, order: usize, edge: Edge) -> Result<Vec<f64>, String> {
+    check_window("savitzky_golay", window)?;
+    if window <= order {
+        return Err(format!("The window of savitzky_golay must be longer than the order {}, got {}", order, window));
+    }
+    let n = y.len();
+    let mut window = window.min(n);
+    if window.is_multiple_of(2) {
+        window = window.saturating_sub(1);
+    }
+    if window <= order {
+        return Ok(y.to_vec());
+    }
+    let half = (window / 2) as isize;
+    let center = savitzky_golay_coefficients(window, order, half as usize);
+
+    Ok((0..n).map(|i| {
+        let inside = i as isize >= half && i as isize + half < n as isize;
+        if inside || edge != Edge::Shrink {
+            (-half..=half).zip(center.iter())
+                .map(|(k, c)| c * y[edge_index(i as isize + k, n, edge).unwrap()])
+                .sum()
+        } else {
+            // polynomial through the first or last full window, evaluated at i
+            let start = if (i as isize) < half { 0 } else { n - window };
+            let c = savitzky_golay_coefficients(window, order, i - start);
+            (0..window).map(|j| c[j] * y[start + j]).sum()
+        }
+    }).collect())
+}
+
+// Weights giving the least squares polynomial over the window evaluated at position
+fn savitzky_golay_coefficients(window: usize, order: usize, position: usize) -> Vec<f64> {
+    let m = order + 1;
+    let t = |j: usize| j as f64 - position as f64;
+    let mut ata = vec![vec![0.0; m]; m];
+    for j in 0..window {
+        for (a, row) in ata.iter_mut().enumerate() {
+            for (b, v) in row.iter_mut().enumerate() {
+                *v += t(j).powi((a + b) as i32);
+            }
+        }
+    }
+    let inv = invert(ata).expect("Singular Savitzky-Golay system");
+    // value at t = 0 is the constant term of the fitted polynomial
+    (0..window).map(|j| (0..m).map(|k| inv[0][k] * t(j).powi(k as i32)).sum()).collect()
+}
+
+fn convolve(y: &[f64], weights: &[f64], half: isize, edge: Edge) -> Vec<f64> {
+    let n = y.len();
+    (0..n).map(|i| {
+        let mut sum = 0.0;
+        let mut norm = 0.0;
+        for k in -half..=half {
+            if let Some(j) = edge_index(i as isize + k, n, edge) {
+                let w = weights[(k + half) as usize];
+                sum += w * y[j];
+                norm += w;
+            }
+        }
+        sum / norm
+    }).collect()
+}
+
+fn edge_index(i: isize, n: usize, edge: Edge) -> Option<usize> {
+    let last = n as isize - 1;
+    if (0..=last).contains(&i) {
+        return Some(i as usize);
+    }
+    match edge {
+        Edge::Shrink => None,
+        Edge::Nearest => Some(i.clamp(0, last) as usize),
+        Edge::Reflect => {
+            if last == 0 {
+                return Some(0);
+            }
+            // reflection is periodic with period 2*last
+            let period = 2 * last;
+            let r = i.rem_euclid(period);
+            Some(if r <= last { r } else { period - r } as usize)
+        },
+    }
+}
+
+// line_plot of the smoothed series, optionally with the raw data underneath as faint markers
+pub fn smoothed_plot<'a, 'b, R: IntoSeries<'a>, S: IntoSeries<'b>>(raw: R, smoothed: S, show_raw: bool, plot_par: &PlotPar) {
+    let raw = raw.into_series();
+    let smoothed = smoothed.into_series();
+    let style = Style::new(plot_par);
+    let mut figure = Figure::new(make_layout(plot_par, &style));
+
+    if show_raw {
+        for l in 0..raw.len() {
+            let markers = Scatter::new(Vec::<f64>::new(), Vec::<f64>::new())
+                .name(&plot_par.legends[l])
+                .show_legend(false)
+                .mode(Mode::Markers)
+                .opacity(0.3)
+                .marker(Marker::new().size(style.msize).color(color(plot_par, l)).symbol(MarkerSymbol::Circle));
+            figure.add_trace(Trace::new(markers, vec![("x", Cow::Borrowed(raw.x(l))), ("y", Cow::Borrowed(raw.y(l)))]));
+        }
+    }
+    for l in 0..smoothed.len() {
+        figure.add_trace(make_trace(smoothed.x(l), smoothed.y(l), l, plot_par, &style));
+    }
+
+    write_plot(&figure, plot_par);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        let y = [1.0, 2.0, 6.0, 4.0, 5.0];
+        assert_eq!(moving_average(&y, 3, Edge::Nearest), Ok(vec![4.0 / 3.0, 3.0, 4.0, 5.0, 14.0 / 3.0]));
+        assert_eq!(moving_average(&y, 3, Edge::Shrink), Ok(vec![1.5, 3.0, 4.0, 5.0, 4.5]));
+        assert_eq!(median(&y, 3, Edge::Reflect), Ok(vec![2.0, 2.0, 4.0, 5.0, 4.0]));
+        assert_eq!(median(&y, 1, Edge::Reflect), Ok(y.to_vec()));
+        // longer than the series
+        assert_eq!(moving_average(&y, 11, Edge::Shrink), Ok(vec![3.6; 5]));
+    }
+
+    #[test]
+    fn savitzky_golay_windows() {
+        // a quadratic is kept by a quadratic fit, also at the ends
+        let y: Vec<f64> = (0..9).map(|i| (i * i) as f64).collect();
+        for edge in [Edge::Shrink, Edge::Reflect] {
+            let smoothed = savitzky_golay(&y, 5, 2, edge).unwrap();
+            let inside = if edge == Edge::Shrink { 0..9 } else { 2..7 };
+            assert!(inside.clone().all(|i| (smoothed[i] - y[i]).abs() < 1e-9), "{:?}", edge);
+        }
+        // cut to the series, and no more points than the order
+        assert_eq!(savitzky_golay(&y[..4], 7, 2, Edge::Shrink).unwrap().len(), 4);
+        assert_eq!(savitzky_golay(&y[..2], 5, 2, Edge::Shrink), Ok(y[..2].to_vec()));
+        assert_eq!(savitzky_golay(&[], 5, 2, Edge::Shrink), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn window_errors() {
+        assert_eq!(moving_average(&[1.0, 2.0], 4, Edge::Nearest), Err("The window of moving_average must be odd, got 4".to_string()));
+        assert_eq!(median(&[1.0, 2.0], 0, Edge::Nearest), Err("The window of median must be odd, got 0".to_string()));
+        assert_eq!(savitzky_golay(&[1.0, 2.0], 6, 2, Edge::Nearest), Err("The window of savitzky_golay must be odd, got 6".to_string()));
+        assert_eq!(savitzky_golay(&[1.0, 2.0], 3, 3, Edge::Nearest),
+            Err("The window of savitzky_golay must be longer than the order 3, got 3".to_string()));
+    }
+}
@@ -0,0 +1,91 @@
+// Re-rendering figures while a run writes their data: the files are polled, which also works
+// on network and container mounts where change notifications don't arrive
+use std::any::Any;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct WatchOptions {
+    // how often the files are checked
+    pub poll: Duration,
+    // files must be unchanged this long before rendering, so a burst of writes renders once
+    pub debounce: Duration,
+}
+
+impl WatchOptions {
+    pub fn new() -> WatchOptions {
+        WatchOptions {
+            poll: Duration::from_millis(250),
+            debounce: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Calls render now and after every change of the files, until the process is stopped.
+// Files may be missing for a while, e.g. before the run creates them. A render that panics
+// or returns an error is reported on stderr with its message and the watch goes on.
+pub fn watch<P: AsRef<Path>, F: FnMut() -> Result<(), String>>(paths: &[P], options: &WatchOptions, mut render: F) -> ! {
+    let paths: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+    let mut rendered = stamps(&paths);
+    run(&mut render);
+    let mut last = rendered.clone();
+    let mut changed_at = Instant::now();
+    loop {
+        thread::sleep(options.poll);
+        let current = stamps(&paths);
+        if current != last {
+            last = current;
+            changed_at = Instant::now();
+        } else if last != rendered && changed_at.elapsed() >= options.debounce {
+            rendered = last.clone();
+            run(&mut render);
+        }
+    }
+}
+
+fn run<F: FnMut() -> Result<(), String>>(render: &mut F) {
+    let message = match catch_panic(render) {
+        Ok(()) => "rendered".to_string(),
+        Err(message) => message,
+    };
+    eprintln!("watch: {}, waiting for changes", message);
+}
+
+// A failed render as its error or panic message; the panic hook still reports a panic as usual
+pub(crate) fn catch_panic<F: FnOnce() -> Result<(), String>>(render: F) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(render)).unwrap_or_else(|payload| Err(panic_message(payload.as_ref())))
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<String>().cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_else(|| "render failed".to_string())
+}
+
+// Modification time and size of every file, None while it doesn't exist
+fn stamps(paths: &[PathBuf]) -> Vec<Option<(SystemTime, u64)>> {
+    paths.iter()
+        .map(|path| fs::metadata(path).ok().map(|m| (m.modified().unwrap_or(SystemTime::UNIX_EPOCH), m.len())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_renders() {
+        assert_eq!(catch_panic(|| Ok(())), Ok(()));
+        assert_eq!(catch_panic(|| Err("no data".to_string())), Err("no data".to_string()));
+        assert_eq!(catch_panic(|| panic!("Can't write {}", 1)), Err("Can't write 1".to_string()));
+        assert_eq!(catch_panic(|| std::panic::panic_any(1)), Err("render failed".to_string()));
+    }
+}
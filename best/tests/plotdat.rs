@@ -0,0 +1,26 @@
+// plotdat reports unreadable data files with their path and a non-zero exit status, without a panic
+use std::fs;
+use std::process::Command;
+
+fn plotdat(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_plotdat")).args(args).output().unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).to_string())
+}
+
+#[test]
+fn missing_file() {
+    let (code, stderr) = plotdat(&["/nonexistent/IV_curve.dat"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.starts_with("plotdat: /nonexistent/IV_curve.dat: "), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn malformed_file() {
+    let path = std::env::temp_dir().join(format!("plotdat-{}.dat", std::process::id()));
+    fs::write(&path, "1 2\n3 x4\n").unwrap();
+    let (code, stderr) = plotdat(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(code, Some(2));
+    assert_eq!(stderr.trim(), format!("plotdat: {}: can't parse \"x4\" at line 2, column 3", path.display()));
+}